@@ -0,0 +1,256 @@
+//! Apportionment of seats among `Area`s by population.
+use crate::core::*;
+use std::collections::HashMap;
+
+pub mod paradox;
+
+/// A way of apportioning seats among `Area`s.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Method {
+    /// Largest remainder with the Hare quota.
+    Hamilton,
+    /// Highest averages with divisors 1, 2, 3, ... (D'Hondt).
+    Jefferson,
+    /// Highest averages with divisors 1, 3, 5, ... (Sainte-Laguë).
+    Webster,
+    /// Highest averages with divisors √(n(n+1)). Used for the U.S. House.
+    HuntingtonHill,
+    /// Highest averages with divisors 0, 1, 2, ...
+    Adams,
+    /// Canada's formula from section 51 of the Constitution Act, 1867.
+    Canada(CanadaRules),
+}
+
+impl Method {
+    /// Methods that don't need any extra data.
+    pub const GENERAL: [Method; 5] = [Method::Hamilton, Method::Jefferson, Method::Webster, Method::HuntingtonHill, Method::Adams];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Method::Hamilton => "Hamilton",
+            Method::Jefferson => "Jefferson",
+            Method::Webster => "Webster",
+            Method::HuntingtonHill => "Huntington-Hill",
+            Method::Adams => "Adams",
+            Method::Canada(_) => "Canada",
+        }
+    }
+
+    /// Finds a method by its lowercase name, e.g. `huntington-hill`.
+    /// `canada` uses `CanadaRules::current`.
+    pub fn from_name(name: &str) -> Option<Method> {
+        match name {
+            "canada" => Some(Method::Canada(CanadaRules::current())),
+            _ => Method::GENERAL.iter().find(|m| m.name().to_lowercase() == name).cloned(),
+        }
+    }
+
+    /// Divisor used by highest averages methods for an area that has `n` seats.
+    fn divisor(&self, n: SeatCount) -> Option<f64> {
+        let n = n as f64;
+        match self {
+            Method::Jefferson => Some(n + 1.0),
+            Method::Webster => Some(n + 0.5),
+            Method::HuntingtonHill => Some((n * (n + 1.0)).sqrt()),
+            Method::Adams => Some(n),
+            Method::Hamilton | Method::Canada(_) => None,
+        }
+    }
+}
+
+/// Rules for `Method::Canada`.
+///
+/// Each province gets its population divided by the electoral quotient,
+/// rounded up if the remainder is more than one half and down otherwise,
+/// but no fewer seats than its senators (senatorial clause) or than its
+/// seats in a reference Parliament (grandfather clause). Areas in `fixed`,
+/// i.e. the territories, get exactly that many seats.
+/// The representation rule is not applied.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CanadaRules {
+    /// Electoral quotient. If `None`, it is derived from the house size as
+    /// the population of all provinces divided by the seats left after `fixed`.
+    pub quotient: Option<u32>,
+    pub senatorial: HashMap<AreaID, SeatCount>,
+    pub grandfather: HashMap<AreaID, SeatCount>,
+    pub fixed: HashMap<AreaID, SeatCount>,
+}
+
+impl CanadaRules {
+    /// Rules after the Preserving Provincial Representation in the House of Commons Act (2022),
+    /// with the grandfather clause based on the 43rd Parliament.
+    /// Areas use Elections Canada's province codes, as in `source::canada`.
+    pub fn current() -> CanadaRules {
+        CanadaRules {
+            quotient: None,
+            senatorial: [(10, 6), (11, 4), (12, 10), (13, 10), (24, 24), (35, 24), (46, 6), (47, 6), (48, 6), (59, 6)].iter().copied().collect(),
+            grandfather: [(10, 7), (11, 4), (12, 11), (13, 10), (24, 78), (35, 121), (46, 14), (47, 14), (48, 34), (59, 42)].iter().copied().collect(),
+            fixed: [(60, 1), (61, 1), (62, 1)].iter().copied().collect(),
+        }
+    }
+}
+
+/// Apportions `seats` among areas by population.
+///
+/// With `Method::Canada` the result may have more than `seats` seats,
+/// since the senatorial and grandfather clauses add seats on top.
+pub fn apportion(method: &Method, pops: &HashMap<AreaID, u32>, seats: SeatCount) -> HashMap<AreaID, SeatCount> {
+    let mut areas: Vec<AreaID> = pops.keys().copied().collect();
+    areas.sort();
+    let pops_vec: Vec<u32> = areas.iter().map(|area| pops[area]).collect();
+
+    let seats_vec = match method {
+        Method::Hamilton => largest_remainder(&pops_vec, seats),
+        Method::Canada(rules) => return canada(rules, pops, seats),
+        _ => highest_averages(method, &pops_vec, seats),
+    };
+
+    areas.into_iter().zip(seats_vec.iter().copied()).collect()
+}
+
+fn largest_remainder(pops: &[u32], seats: SeatCount) -> Box<[SeatCount]> {
+    let total_pop: u64 = pops.iter().map(|&x| x as u64).sum();
+    if total_pop == 0 {
+        return vec![0; pops.len()].into_boxed_slice();
+    }
+
+    let quotas: Vec<f64> = pops.iter().map(|&pop| pop as f64 * seats as f64 / total_pop as f64).collect();
+    let mut seat_arr: Box<[SeatCount]> = quotas.iter().map(|&q| q.floor() as SeatCount).collect();
+
+    let mut order: Vec<usize> = (0..pops.len()).collect();
+    order.sort_by(|&a, &b| (quotas[b] - quotas[b].floor()).partial_cmp(&(quotas[a] - quotas[a].floor())).unwrap().then(a.cmp(&b)));
+
+    let given: SeatCount = seat_arr.iter().sum();
+    for &i in order.iter().take((seats - given) as usize) {
+        seat_arr[i] += 1;
+    }
+    seat_arr
+}
+
+fn highest_averages(method: &Method, pops: &[u32], seats: SeatCount) -> Box<[SeatCount]> {
+    let mut seat_arr = vec![0 as SeatCount; pops.len()].into_boxed_slice();
+
+    for _ in 0..seats {
+        let mut best: Option<(usize, f64)> = None;
+        for (i, &pop) in pops.iter().enumerate() {
+            let divisor = method.divisor(seat_arr[i]).unwrap();
+            let priority = if divisor == 0.0 { f64::INFINITY } else { pop as f64 / divisor };
+            if best.map(|(_, p)| priority > p).unwrap_or(true) {
+                best = Some((i, priority));
+            }
+        }
+
+        match best {
+            Some((i, _)) => seat_arr[i] += 1,
+            None => break
+        }
+    }
+
+    seat_arr
+}
+
+fn canada(rules: &CanadaRules, pops: &HashMap<AreaID, u32>, seats: SeatCount) -> HashMap<AreaID, SeatCount> {
+    let fixed_seats: SeatCount = rules.fixed.iter().filter(|(area, _)| pops.contains_key(area)).map(|(_, &s)| s).sum();
+    let provinces_pop: u64 = pops.iter().filter(|(area, _)| !rules.fixed.contains_key(area)).map(|(_, &pop)| pop as u64).sum();
+    let quotient = rules.quotient.map(|q| q as u64)
+        .unwrap_or_else(|| provinces_pop / seats.saturating_sub(fixed_seats).max(1) as u64)
+        .max(1);
+
+    pops.iter().map(|(&area, &pop)| {
+        if let Some(&fixed) = rules.fixed.get(&area) {
+            return (area, fixed);
+        }
+
+        // Rule 1 of section 51: a remainder greater than one half counts as one more seat.
+        let pop = pop as u64;
+        let seats = (pop / quotient + if (pop % quotient) * 2 > quotient { 1 } else { 0 }) as SeatCount;
+        let seats = seats
+            .max(rules.senatorial.get(&area).copied().unwrap_or(0))
+            .max(rules.grandfather.get(&area).copied().unwrap_or(0));
+        (area, seats)
+    }).collect()
+}
+
+/// Population of `area`, adding up the areas inside it if it has none of its own.
+fn area_population(stage: &ElectionStage, area: AreaID) -> Option<u32> {
+    if let Some(pop) = stage.areas.get(&area).and_then(|a| a.population) {
+        return Some(pop);
+    }
+
+    let children: Vec<AreaID> = stage.areas.iter().filter(|(_, a)| a.parent == Some(area)).map(|(&id, _)| id).collect();
+    if children.is_empty() {
+        return None;
+    }
+    children.into_iter().map(|child| area_population(stage, child)).sum()
+}
+
+/// Populations of the areas at `level` (see `ElectionStage::areas_at_level`),
+/// or an error naming an area without one.
+pub fn area_populations(stage: &ElectionStage, level: usize) -> Result<HashMap<AreaID, u32>, Error> {
+    stage.areas_at_level(level).into_iter().map(|id| {
+        area_population(stage, id).map(|pop| (id, pop)).ok_or(Error::Method(format!("area {} has no population", stage.areas[&id].name)))
+    }).collect()
+}
+
+/// Total seats for each area at `level` for a house of `seats` seats.
+/// For single-member systems this is the number of districts each area would have.
+pub fn area_seats(stage: &ElectionStage, method: &Method, seats: SeatCount, level: usize) -> Result<HashMap<AreaID, SeatCount>, Error> {
+    Ok(apportion(method, &area_populations(stage, level)?, seats))
+}
+
+/// Sets `Area::seats` so that each area has `totals[area]` seats, including
+/// the seats of its districts and of the areas inside it, e.g. after `area_seats`.
+pub fn apply_area_seats(stage: &mut ElectionStage, totals: &HashMap<AreaID, SeatCount>) {
+    let inner_seats: HashMap<AreaID, SeatCount> = totals.keys().map(|&id| {
+        let districts: SeatCount = stage.districts_in(id).iter().filter_map(|d| stage.districts.get(d)).map(|d| d.seats).sum();
+        let areas: SeatCount = stage.areas.iter()
+            .filter(|(&other, _)| other != id && stage.ancestors(other).contains(&id))
+            .map(|(_, a)| a.seats)
+            .sum();
+        (id, districts + areas)
+    }).collect();
+
+    for (id, area) in stage.areas.iter_mut() {
+        if let Some(&total) = totals.get(id) {
+            area.seats = total.saturating_sub(inner_seats[id]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(quotient: Option<u32>) -> CanadaRules {
+        CanadaRules { quotient, senatorial: HashMap::new(), grandfather: HashMap::new(), fixed: HashMap::new() }
+    }
+
+    fn pops(pops: &[(AreaID, u32)]) -> HashMap<AreaID, u32> {
+        pops.iter().copied().collect()
+    }
+
+    #[test]
+    fn canada_rounds_to_nearest() {
+        let seats = apportion(&Method::Canada(rules(Some(100))), &pops(&[(1, 249), (2, 250), (3, 251), (4, 49), (5, 51)]), 10);
+        let expected: HashMap<AreaID, SeatCount> = [(1, 2), (2, 2), (3, 3), (4, 0), (5, 1)].iter().copied().collect();
+        assert_eq!(seats, expected);
+    }
+
+    #[test]
+    fn canada_clauses_add_seats() {
+        let mut rules = rules(Some(100));
+        rules.senatorial.insert(1, 4);
+        rules.grandfather.insert(2, 7);
+        let seats = apportion(&Method::Canada(rules), &pops(&[(1, 120), (2, 500), (3, 900)]), 10);
+        assert_eq!((seats[&1], seats[&2], seats[&3]), (4, 7, 9));
+    }
+
+    #[test]
+    fn canada_quotient_leaves_out_fixed_areas() {
+        let mut rules = rules(None);
+        rules.fixed.insert(9, 1);
+        // 4000 people in the provinces for 5 - 1 seats gives a quotient of 1000.
+        let seats = apportion(&Method::Canada(rules), &pops(&[(1, 1000), (2, 3000), (9, 40)]), 5);
+        assert_eq!((seats[&1], seats[&2], seats[&9]), (1, 3, 1));
+    }
+}
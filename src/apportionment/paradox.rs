@@ -0,0 +1,182 @@
+//! Detection of apportionment paradoxes and quota violations.
+use super::*;
+use std::ops::RangeInclusive;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Paradox {
+    /// `area` loses a seat when the house grows from `seats` to `seats + 1`.
+    Alabama { seats: SeatCount, area: AreaID },
+    /// With a house of `seats`, `grew` grows faster than `other` between the
+    /// two populations, yet `grew` loses a seat while `other` gains one.
+    Population { seats: SeatCount, grew: AreaID, other: AreaID },
+    /// Adding `new_area` to a house of `seats`, together with the seats it
+    /// would get, moves seats between other areas. `changes` lists the
+    /// seats gained or lost by each affected area.
+    NewStates { seats: SeatCount, new_area: AreaID, changes: Vec<(AreaID, i32)> },
+    /// With a house of `seats`, `area` gets `got` seats, which is not
+    /// its quota rounded up or down.
+    Quota { seats: SeatCount, area: AreaID, quota: f64, got: SeatCount },
+}
+
+impl Paradox {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Paradox::Alabama { .. } => "Alabama paradox",
+            Paradox::Population { .. } => "population paradox",
+            Paradox::NewStates { .. } => "new states paradox",
+            Paradox::Quota { .. } => "quota violation",
+        }
+    }
+
+    /// Describes the paradox, using `name` to name areas.
+    pub fn describe(&self, name: impl Fn(AreaID) -> String) -> String {
+        match self {
+            Paradox::Alabama { seats, area } =>
+                format!("{} loses a seat when the house grows from {} to {}", name(*area), seats, seats + 1),
+            Paradox::Population { seats, grew, other } =>
+                format!("with {} seats, {} grows faster than {} but loses a seat to it", seats, name(*grew), name(*other)),
+            Paradox::NewStates { seats, new_area, changes } =>
+                format!("adding {} to {} seats changes {}", name(*new_area), seats,
+                    changes.iter().map(|(area, change)| format!("{} by {:+}", name(*area), change)).collect::<Vec<_>>().join(", ")),
+            Paradox::Quota { seats, area, quota, got } =>
+                format!("with {} seats, {} gets {} seats for a quota of {:.2}", seats, name(*area), got, quota),
+        }
+    }
+}
+
+/// Paradoxes found for one method.
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub method: Method,
+    pub paradoxes: Vec<Paradox>,
+}
+
+impl Report {
+    /// Number of paradoxes with the given `Paradox::kind`.
+    pub fn count(&self, kind: &str) -> usize {
+        self.paradoxes.iter().filter(|p| p.kind() == kind).count()
+    }
+}
+
+/// Looks for paradoxes with every house size in `sizes`.
+/// The population paradox is only checked if `later` populations are given.
+pub fn analyse(method: &Method, pops: &HashMap<AreaID, u32>, sizes: RangeInclusive<SeatCount>, later: Option<&HashMap<AreaID, u32>>) -> Report {
+    let mut areas: Vec<AreaID> = pops.keys().copied().collect();
+    areas.sort();
+
+    let mut paradoxes = Vec::new();
+    let mut prev: Option<HashMap<AreaID, SeatCount>> = None;
+    for seats in sizes {
+        let result = apportion(method, pops, seats);
+
+        if let Some(prev) = &prev {
+            for &area in areas.iter() {
+                if result[&area] < prev[&area] {
+                    paradoxes.push(Paradox::Alabama { seats: seats - 1, area });
+                }
+            }
+        }
+
+        if let Some(later) = later {
+            paradoxes.extend(population_paradoxes(method, &areas, pops, later, &result, seats));
+        }
+        paradoxes.extend(new_states_paradoxes(method, &areas, pops, &result, seats));
+        paradoxes.extend(quota_violations(&areas, pops, &result, seats));
+
+        prev = Some(result);
+    }
+
+    Report { method: method.clone(), paradoxes }
+}
+
+/// Compares `result` with the apportionment of `later` populations. Only areas in
+/// `later` with a population now are compared, so if that leaves any out, both
+/// apportionments are made over those areas alone.
+fn population_paradoxes(method: &Method, areas: &[AreaID], pops: &HashMap<AreaID, u32>, later: &HashMap<AreaID, u32>, result: &HashMap<AreaID, SeatCount>, seats: SeatCount) -> Vec<Paradox> {
+    let areas: Vec<AreaID> = areas.iter().copied().filter(|area| later.contains_key(area) && pops[area] > 0).collect();
+    let later_pops: HashMap<AreaID, u32> = areas.iter().map(|area| (*area, later[area])).collect();
+    let later_result = apportion(method, &later_pops, seats);
+    let subset_result;
+    let result = if areas.len() == pops.len() {
+        result
+    } else {
+        let earlier_pops: HashMap<AreaID, u32> = areas.iter().map(|area| (*area, pops[area])).collect();
+        subset_result = apportion(method, &earlier_pops, seats);
+        &subset_result
+    };
+    let growth = |area: &AreaID| later[area] as f64 / pops[area] as f64;
+
+    let mut paradoxes = Vec::new();
+    for grew in areas.iter() {
+        if later_result[grew] >= result[grew] {
+            continue
+        }
+        for other in areas.iter() {
+            if later_result[other] > result[other] && growth(grew) > growth(other) {
+                paradoxes.push(Paradox::Population { seats, grew: *grew, other: *other });
+            }
+        }
+    }
+    paradoxes
+}
+
+fn new_states_paradoxes(method: &Method, areas: &[AreaID], pops: &HashMap<AreaID, u32>, result: &HashMap<AreaID, SeatCount>, seats: SeatCount) -> Vec<Paradox> {
+    let mut paradoxes = Vec::new();
+    for &new_area in areas.iter() {
+        let mut without = pops.clone();
+        without.remove(&new_area);
+        let before = apportion(method, &without, seats.saturating_sub(result[&new_area]));
+
+        let changes: Vec<(AreaID, i32)> = areas.iter()
+            .filter(|&&area| area != new_area && result[&area] != before[&area])
+            .map(|&area| (area, result[&area] as i32 - before[&area] as i32))
+            .collect();
+        if !changes.is_empty() {
+            paradoxes.push(Paradox::NewStates { seats, new_area, changes });
+        }
+    }
+    paradoxes
+}
+
+fn quota_violations(areas: &[AreaID], pops: &HashMap<AreaID, u32>, result: &HashMap<AreaID, SeatCount>, seats: SeatCount) -> Vec<Paradox> {
+    let total_pop: u64 = pops.values().map(|&x| x as u64).sum();
+    if total_pop == 0 {
+        return Vec::new();
+    }
+
+    areas.iter().filter_map(|&area| {
+        let quota = pops[&area] as f64 * seats as f64 / total_pop as f64;
+        let got = result[&area];
+        if (got as f64) < quota.floor() || (got as f64) > quota.ceil() {
+            Some(Paradox::Quota { seats, area, quota, got })
+        } else {
+            None
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn populations(pops: &[(AreaID, u32)]) -> HashMap<AreaID, u32> {
+        pops.iter().copied().collect()
+    }
+
+    #[test]
+    fn unchanged_populations_have_no_population_paradox() {
+        let pops = populations(&[(1, 5_000), (2, 3_000), (3, 2_000)]);
+        let report = analyse(&Method::Hamilton, &pops, 5..=10, Some(&pops));
+        assert_eq!(report.count("population paradox"), 0);
+    }
+
+    #[test]
+    fn areas_missing_later_are_left_out_of_both_apportionments() {
+        // With area 4, area 2 has 1 of 4 seats; without it, none, now as before.
+        // Comparing with the first would make area 2's growth look like a paradox.
+        let pops = populations(&[(1, 867), (2, 199), (3, 830), (4, 885)]);
+        let later = populations(&[(1, 798), (2, 201), (3, 839)]);
+        let report = analyse(&Method::Hamilton, &pops, 4..=4, Some(&later));
+        assert_eq!(report.count("population paradox"), 0);
+    }
+}
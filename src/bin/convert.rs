@@ -0,0 +1,69 @@
+extern crate vsim2;
+use vsim2::core::*;
+
+use std::{env, fs, io};
+use std::path::Path;
+
+/// Formats a dataset can be stored in, told apart by the path's extension.
+enum Format {
+    Elc,
+    /// A directory of JSON files, see `core::json`.
+    Json,
+    /// See `core::tidy`.
+    Csv,
+}
+
+impl Format {
+    /// Paths without an extension, and existing directories, are JSON directories.
+    fn of(path: &Path) -> Result<Format, String> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("elc") => Ok(Format::Elc),
+            Some("csv") => Ok(Format::Csv),
+            None => Ok(Format::Json),
+            Some(_) if path.is_dir() => Ok(Format::Json),
+            Some(ext) => Err(format!("{}: unknown extension .{}, expected .elc, .csv or a directory", path.display(), ext)),
+        }
+    }
+}
+
+fn read(path: &Path, format: Format) -> Result<elc::Contents, Error> {
+    match format {
+        Format::Elc => {
+            let data = fs::read(path).map_err(elc::Error::Io)?;
+            Ok(elc::Dataset::open(&data)?.into_contents()?)
+        },
+        Format::Json => Ok(json::load_contents(path)?),
+        Format::Csv => tidy::read(io::BufReader::new(fs::File::open(path).map_err(|e| Error::Source(Box::new(e)))?)),
+    }
+}
+
+fn write(path: &Path, format: Format, contents: &elc::Contents, compress: bool) -> Result<(), Box<dyn std::error::Error>> {
+    match format {
+        Format::Elc => elc::write_sections(&mut io::BufWriter::new(fs::File::create(path)?), &contents.metadata, &contents.sections(), compress)?,
+        Format::Json => json::save(path, &contents.metadata, &contents.sections())?,
+        Format::Csv => tidy::write(io::BufWriter::new(fs::File::create(path)?), &contents.metadata, &contents.sections())?,
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut paths = Vec::new();
+    let mut compress = true;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--uncompressed" => compress = false,
+            _ => paths.push(arg)
+        }
+    }
+    if paths.len() != 2 {
+        return Err("usage: convert <input> <output> [--uncompressed]\n\
+            Paths ending in .elc are datasets, paths ending in .csv are tidy CSV files, \
+            and paths without an extension are directories of JSON files.".into());
+    }
+    let (input, output) = (Path::new(&paths[0]), Path::new(&paths[1]));
+
+    // Both are checked before anything is read or written.
+    let (input_format, output_format) = (Format::of(input)?, Format::of(output)?);
+    let contents = read(input, input_format)?;
+    write(output, output_format, &contents, compress)
+}
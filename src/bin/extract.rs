@@ -3,38 +3,131 @@ use vsim2::core::*;
 use vsim2::*;
 
 use std::{io, fs, env};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
 use std::error::Error;
+/// Today's date (UTC).
+fn today() -> Date {
+    // Days since 1970-01-01 to a civil date, after Howard Hinnant's `civil_from_days`.
+    let days = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() / 86400).unwrap_or(0) as i64 + 719468;
+    let era = days / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    Date::new(year as u32, month as u8, day as u8)
+}
+
+/// Reads `dataset/<dir>/mapping.toml`, or `mapping.json`.
+fn read_mapping(dir: &str) -> Result<source::generic::Mapping, Box<dyn Error>> {
+    if let Ok(text) = fs::read_to_string(format!("dataset/{}/mapping.toml", dir)) {
+        return Ok(toml::from_str(&text)?);
+    }
+    match fs::read_to_string(format!("dataset/{}/mapping.json", dir)) {
+        Ok(text) => Ok(serde_json::from_str(&text)?),
+        Err(_) => Err(format!("unknown country, and no mapping in dataset/{}", dir).into()),
+    }
+}
+
+/// The built-in party registry, with the overrides in `dataset/<dir>/parties.toml`
+/// or `parties.json`, if there are any.
+fn read_registry(dir: &str) -> Result<core::parties::Registry, Box<dyn Error>> {
+    let mut registry = core::parties::Registry::builtin();
+    for name in ["parties.toml", "parties.json"].iter() {
+        let path = format!("dataset/{}/{}", dir, name);
+        if std::path::Path::new(&path).exists() {
+            registry.merge(core::parties::Registry::load(path.as_ref())?);
+        }
+    }
+    Ok(registry)
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
     let mut args = env::args();
     args.next();
     let country = args.next().ok_or("missing country")?;
+    let mut seed = 0;
+    let mut source_url = None;
+    let mut date: Option<Date> = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--seed" => seed = args.next().ok_or("--seed needs a value")?.parse()?,
+            "--source-url" => source_url = Some(args.next().ok_or("--source-url needs a value")?),
+            "--date" => date = Some(args.next().ok_or("--date needs a value")?.parse()?),
+            _ => return Err(format!("unknown argument {}", arg).into())
+        }
+    }
 
-    let (dir, (stage, results, groupings)) = 
+    let dir = match country.as_str() {
+        "canada" => "canada-2019",
+        "germany" => "germany-2017",
+        dir => dir,
+    };
+    let registry = read_registry(dir)?;
+
+    let (name, election, (mut stage, results, previous, mut groupings)) =
     match country.as_str() {
         "canada" => {
             let file = fs::File::open("dataset/canada-2019/src.zip")?;
-            ("canada-2019", source::canada::from_zip(file, Date::new(2019, 10, 21))?)
+            let (stage, results, groupings) = source::canada::from_zip(file, date.unwrap_or(Date::new(2019, 10, 21)), &registry)?;
+            ("Canada".to_owned(), "2019 federal election".to_owned(), (stage, results, None, groupings))
         },
         "germany" => {
             let file = fs::File::open("dataset/germany-2017/src.csv")?;
-            ("germany-2017", source::germany::from_csv(file)?)
+            ("Germany".to_owned(), "2017 federal election".to_owned(), source::germany::from_csv_with_previous(file, date, &registry)?)
         }
-        _  => {
-            return Err("unknown country".into());
+        // Other datasets are read by `source::generic`, as described by their mapping.
+        _ => {
+            let mapping = read_mapping(dir)?;
+            let file = fs::File::open(format!("dataset/{}/{}", dir, mapping.file.as_deref().unwrap_or("src.csv")))?;
+            let (stage, results, groupings) = source::generic::from_csv(io::BufReader::new(file), &mapping, date, &registry)?;
+            (mapping.country.unwrap_or_else(|| dir.to_owned()), mapping.election.unwrap_or_default(), (stage, results, None, groupings))
         }
     };
 
-    let out_file = fs::File::create(format!("dataset/{}/stage.json", dir))?;
-    serde_json::to_writer_pretty(out_file, &stage)?;
+    // Boundaries and adjacency aren't part of the election data, so they're read from separate files if present.
+    let boundaries: Option<Boundaries> = match fs::File::open(format!("dataset/{}/boundaries.json", dir)) {
+        Ok(file) => Some(serde_json::from_reader(file)?),
+        Err(_) => None
+    };
+    if let Ok(file) = fs::File::open(format!("dataset/{}/adjacency.csv", dir)) {
+        stage.adjacency = Some(source::adjacency::from_csv(file)?);
+    } else if let Some(boundaries) = &boundaries {
+        stage.adjacency = Some(source::adjacency::from_boundaries(boundaries));
+    }
+    if let Ok(file) = fs::File::open(format!("dataset/{}/unit-adjacency.csv", dir)) {
+        stage.unit_adjacency = Some(source::adjacency::from_csv(file)?);
+    }
+
+    // Pairs for DMP, and 3-7 districts for STV-like methods.
+    for &size in [GroupingSize::Exact(2), GroupingSize::Range { min: 3, max: 7 }].iter() {
+        groupings.entry(size.preferred()).or_insert_with(|| grouping::generate(&stage, &results, size, seed));
+    }
 
-    let out_file = fs::File::create(format!("dataset/{}/results.json", dir))?;
-    serde_json::to_writer_pretty(out_file, &results)?;
+    for warning in grouping::validate_all(&groupings, &stage)? {
+        eprintln!("warning: {}", warning);
+    }
+
+    let metadata = core::elc::Metadata {
+        country: Some(name),
+        election: Some(election),
+        source_url,
+        importer: Some(format!("vsim2-extract {}", env!("CARGO_PKG_VERSION"))),
+        created: Some(today()),
+    };
+    // The previous election's results, for the same districts.
+    let other_results: Vec<ElectionResults> = previous.into_iter().collect();
 
-    let out_file = fs::File::create(format!("dataset/{}/groupings.json", dir))?;
-    serde_json::to_writer_pretty(out_file, &groupings)?;
+    // `boundaries.json` is already there, as read above, so the JSON files leave it as it is.
+    let sections = core::elc::Sections { stage: &stage, results: &results, groupings: &groupings, geometry: None, other_results: &other_results };
+    core::json::save(Path::new(&format!("dataset/{}", dir)), &metadata, &sections)?;
 
     let mut out_file = fs::File::create(format!("dataset/{}/data.elc", dir))?;
-    core::encode(&mut out_file, (&stage, &results, &groupings))?;
+    let sections = core::elc::Sections { geometry: boundaries.as_ref(), ..sections };
+    core::elc::write_sections(&mut out_file, &metadata, &sections, true)?;
 
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,16 @@
+extern crate vsim2;
+use vsim2::core::json;
+
+use std::{env, fs};
+use std::path::Path;
+
+/// Writes the JSON Schemas of the JSON dataset files, by default to `schema/`.
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = env::args().nth(1).unwrap_or("schema".to_owned());
+    fs::create_dir_all(&dir)?;
+    for (name, schema) in json::schemas() {
+        let out_file = fs::File::create(Path::new(&dir).join(name))?;
+        serde_json::to_writer_pretty(out_file, &schema)?;
+    }
+    Ok(())
+}
@@ -3,41 +3,263 @@ use vsim2::core::*;
 use vsim2::*;
 
 use std::{env,fs};
+use std::collections::HashMap;
 
 fn terminal_color(clr: u32, fg: bool) -> String {
     format!("\x1b[{}8;2;{};{};{}m", if fg { 3 } else { 4 }, (clr & 0xff0000) >> 16, (clr & 0x00ff00) >> 8, clr & 0x0000ff)
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = env::args();
-    let path = args.skip(1).next().ok_or("input file not specified")?;
-
-    let mut file = fs::File::open(path)?;
-    let (stage, results, groupings) = core::decode(&mut file)?;
-
-    let mut sizes: Vec<_> = groupings.keys().collect();
-    sizes.sort();
-
-    let method = methods::fptp::FPTP;
-    let seats = method.run(&stage, &results, &groupings[&sizes[0]])?;
-
-    let stats = utils::seats_by_party(&stage, &seats);
+fn print_seats(stage: &ElectionStage, stats: &HashMap<Option<PartyID>, usize>, indent: &str) {
     let mut stats_vec: Vec<_> = stats.iter().collect();
     stats_vec.sort_by(|a, b| b.1.cmp(a.1));
 
-//    print!("{}\n", terminal_color(0xffffff, false));
     for (party, &seats) in stats_vec.iter() {
         if let Some(p) = party {
-            print!("{}{}{} => {}\n",
-                terminal_color(stage.parties[&p].color, true),
-                stage.parties[&p].name,
+            println!("{}{}{}{} => {}",
+                indent,
+                terminal_color(stage.parties[p].color, true),
+                stage.parties[p].name,
                 terminal_color(0x000000, true),
                 seats);
         } else {
-            print!("{}<ind>{} => {}\n", terminal_color(0xaaaaaa, true), seats,                 terminal_color(0x000000, true));
+            println!("{}{}<ind>{} => {}", indent, terminal_color(0xaaaaaa, true), terminal_color(0x000000, true), seats);
+        }
+    }
+}
+
+fn print_paradoxes(stage: &ElectionStage, methods: &[apportionment::Method], sizes: std::ops::RangeInclusive<SeatCount>, level: usize, later: Option<&HashMap<AreaID, u32>>) -> Result<(), core::Error> {
+    let pops = apportionment::area_populations(stage, level)?;
+    let name = |area: AreaID| stage.areas.get(&area).map(|a| a.name.clone()).unwrap_or(format!("area {}", area));
+    let kinds = ["Alabama paradox", "population paradox", "new states paradox", "quota violation"];
+
+    for method in methods {
+        let report = apportionment::paradox::analyse(method, &pops, sizes.clone(), later);
+        println!("\x1b[1m{}\x1b[0m", method.name());
+        for kind in kinds.iter() {
+            if *kind == "population paradox" && later.is_none() {
+                continue
+            }
+            println!("  {}: {}", kind, report.count(kind));
+            for paradox in report.paradoxes.iter().filter(|p| p.kind() == *kind).take(5) {
+                println!("    {}", paradox.describe(name));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Generates the groupings `method` needs, for data without any.
+fn generate_groupings(stage: &ElectionStage, results: &ElectionResults, method: &dyn ElectoralMethod, seed: u64) -> Groupings {
+    let mut groupings = Groupings::new();
+    for &size in [GroupingSize::Exact(1)].iter().chain(method.grouping_sizes().iter()) {
+        groupings.insert(size.preferred(), grouping::generate(stage, results, size, seed));
+    }
+    groupings
+}
+
+/// Runs `method` on `maps` redrawn maps and prints the range of seats each party wins.
+fn print_redistricting(stage: &ElectionStage, results: &ElectionResults, method: &dyn ElectoralMethod, maps: u64, options: &redistricting::Options) -> Result<(), Box<dyn std::error::Error>> {
+    let mut seats: HashMap<Option<PartyID>, Vec<usize>> = HashMap::new();
+    for i in 0..maps {
+        let options = redistricting::Options { seed: options.seed + i, ..options.clone() };
+        let (stage, results) = redistricting::redistrict(stage, results, &options)?;
+
+        let groupings = generate_groupings(&stage, &results, method, options.seed);
+        let grouping = core::select_groupings(&stage, &groupings, &method.grouping_sizes())?;
+        let by_party = utils::seats_by_party(&stage, &method.run(&stage, &results, &grouping)?)?;
+
+        for (&party, &n) in by_party.iter() {
+            seats.entry(party).or_insert_with(|| vec![0; i as usize]).push(n);
+        }
+        for list in seats.values_mut() {
+            list.resize(i as usize + 1, 0);
+        }
+    }
+
+    let mut parties: Vec<_> = seats.into_iter().collect();
+    parties.sort_by_key(|(_, list)| std::cmp::Reverse(list.iter().sum::<usize>()));
+    println!("\x1b[1m{} maps\x1b[0m", maps);
+    for (party, list) in parties {
+        let (name, color) = match party {
+            Some(p) => (stage.parties[&p].name.as_str(), stage.parties[&p].color),
+            None => ("<ind>", 0xaaaaaa),
+        };
+        let mean = list.iter().sum::<usize>() as f64 / list.len() as f64;
+        println!("{}{}{} => {}-{} (mean {:.1})", terminal_color(color, true), name, terminal_color(0x000000, true),
+            list.iter().min().unwrap(), list.iter().max().unwrap(), mean);
+    }
+
+    Ok(())
+}
+
+/// Runs `method` on every election in the dataset and prints each party's seats per election.
+fn print_elections(stage: &ElectionStage, elections: &[ElectionResults], groupings: &Groupings, method: &dyn ElectoralMethod) -> Result<(), Box<dyn std::error::Error>> {
+    let grouping = core::select_groupings(stage, groupings, &method.grouping_sizes())?;
+    let mut seats: HashMap<Option<PartyID>, Vec<usize>> = HashMap::new();
+    for (i, results) in elections.iter().enumerate() {
+        let by_party = utils::seats_by_party(stage, &method.run(stage, results, &grouping)?)?;
+        for (&party, &n) in by_party.iter() {
+            seats.entry(party).or_insert_with(|| vec![0; elections.len()])[i] = n;
         }
     }
 
+    let mut parties: Vec<_> = seats.into_iter().collect();
+    parties.sort_by_key(|(_, list)| std::cmp::Reverse(list.iter().sum::<usize>()));
+    let width = parties.iter().map(|(party, _)| party.map(|p| stage.parties[&p].name.chars().count()).unwrap_or(5)).max().unwrap_or(0);
+    print!("\x1b[1m{:width$}", "", width = width);
+    for results in elections {
+        print!("  {}", results.date);
+    }
+    println!("\x1b[0m");
+    for (party, list) in parties {
+        let (name, color) = match party {
+            Some(p) => (stage.parties[&p].name.as_str(), stage.parties[&p].color),
+            None => ("<ind>", 0xaaaaaa),
+        };
+        print!("{}{:width$}{}", terminal_color(color, true), name, terminal_color(0x000000, true), width = width);
+        for n in list {
+            print!("  {:>10}", n);
+        }
+        println!();
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut path = None;
+    let mut config = methods::MethodConfig { method: "fptp".to_owned(), params: Params::new() };
+    let mut params: Vec<(String, String)> = Vec::new();
+    let mut paradoxes: Option<(SeatCount, SeatCount)> = None;
+    let mut apportionment_methods: Vec<apportionment::Method> = apportionment::Method::GENERAL.to_vec();
+    let mut later_path: Option<String> = None;
+    let mut transpose_path: Option<String> = None;
+    let mut level: Option<usize> = None;
+    let mut maps: Option<u64> = None;
+    let mut elections = false;
+    let mut date: Option<Date> = None;
+    let mut redistricting_options = redistricting::Options::default();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--list-methods" => {
+                for info in methods::METHODS.iter() {
+                    print!("{}\t{}\n    {}\n", info.id, info.name, info.description);
+                    if !info.examples.is_empty() {
+                        println!("    Used in: {}", info.examples.join(", "));
+                    }
+                    for spec in (info.create)().param_specs() {
+                        println!("    --param {}=<{}> (default {}): {}", spec.name, spec.type_, spec.default, spec.description);
+                    }
+                }
+                return Ok(());
+            },
+            "--method" => config.method = args.next().ok_or("--method needs a value")?,
+            "--scenario" => {
+                let scenario = args.next().ok_or("--scenario needs a file")?;
+                let text = fs::read_to_string(&scenario)?;
+                config = if scenario.ends_with(".toml") {
+                    toml::from_str(&text)?
+                } else {
+                    serde_json::from_str(&text)?
+                };
+            },
+            "--param" => {
+                let param = args.next().ok_or("--param needs a value")?;
+                let (name, value) = param.split_once('=').ok_or("--param must look like name=value")?;
+                params.push((name.to_owned(), value.to_owned()));
+            },
+            "--paradoxes" => {
+                let range = args.next().ok_or("--paradoxes needs a range of house sizes")?;
+                let (min, max) = range.split_once('-').ok_or("--paradoxes must look like min-max")?;
+                paradoxes = Some((min.parse()?, max.parse()?));
+            },
+            "--apportionment" => {
+                apportionment_methods = args.next().ok_or("--apportionment needs a list of methods")?.split(',')
+                    .map(|name| apportionment::Method::from_name(name).ok_or(format!("unknown apportionment method {}", name)))
+                    .collect::<Result<_, _>>()?;
+            },
+            "--level" => level = Some(args.next().ok_or("--level needs a value")?.parse()?),
+            "--later" => later_path = Some(args.next().ok_or("--later needs a file")?),
+            "--transpose" => transpose_path = Some(args.next().ok_or("--transpose needs a weight table")?),
+            "--redistrict" => maps = Some(args.next().ok_or("--redistrict needs a number of maps")?.parse()?),
+            "--tolerance" => redistricting_options.tolerance = args.next().ok_or("--tolerance needs a value")?.parse()?,
+            "--steps" => redistricting_options.steps = args.next().ok_or("--steps needs a value")?.parse()?,
+            "--compactness" => redistricting_options.compactness = args.next().ok_or("--compactness needs a value")?.parse()?,
+            "--seed" => redistricting_options.seed = args.next().ok_or("--seed needs a value")?.parse()?,
+            "--elections" => elections = true,
+            "--date" => date = Some(args.next().ok_or("--date needs a value")?.parse()?),
+            _ => path = Some(arg)
+        }
+    }
+    let path = path.ok_or("input file not specified")?;
+
+    let mut method = config.build()?;
+    for (name, value) in params.iter() {
+        let spec = method.param_specs().into_iter().find(|spec| spec.name == name).ok_or(format!("unknown parameter {}", name))?;
+        method.set_param(spec.name, spec.parse(value)?)?;
+    }
+
+    let data = fs::read(path)?;
+    let mut dataset = core::elc::Dataset::open(&data)?;
+    let mut all_results = if elections { dataset.all_results()? } else { Vec::new() };
+    let selected = match date {
+        Some(date) => Some(dataset.results_on(date)?),
+        None => None
+    };
+    let (mut stage, mut results, mut groupings) = dataset.into_data()?;
+    if let Some(selected) = selected {
+        results = selected;
+    }
+    if let Some(transpose_path) = transpose_path {
+        let table = source::transposition::from_csv(fs::File::open(transpose_path)?, &stage)?;
+        // All elections at once, so that they share the new candidates.
+        let elections: Vec<&ElectionResults> = std::iter::once(&results).chain(all_results.iter()).collect();
+        let (new_stage, mut new_results) = redistricting::transposition::transpose(&stage, &elections, &table)?;
+        results = new_results.remove(0);
+        all_results = new_results;
+        groupings = generate_groupings(&new_stage, &results, method.as_ref(), redistricting_options.seed);
+        stage = new_stage;
+    }
+    for warning in grouping::validate_all(&groupings, &stage)? {
+        eprintln!("warning: {}", warning);
+    }
+
+    if let Some((min, max)) = paradoxes {
+        let later = match later_path {
+            Some(later_path) => Some(apportionment::area_populations(&core::decode(&mut fs::File::open(later_path)?)?.0, level.unwrap_or(0))?),
+            None => None
+        };
+        print_paradoxes(&stage, &apportionment_methods, min..=max, level.unwrap_or(0), later.as_ref())?;
+        return Ok(());
+    }
+
+    if elections {
+        print_elections(&stage, &all_results, &groupings, method.as_ref())?;
+        return Ok(());
+    }
+
+    if let Some(maps) = maps {
+        print_redistricting(&stage, &results, method.as_ref(), maps, &redistricting_options)?;
+        return Ok(());
+    }
+
+    let grouping = core::select_groupings(&stage, &groupings, &method.grouping_sizes())?;
+    let seats = method.run(&stage, &results, &grouping)?;
+
+    if let Some(level) = level {
+        let by_area = utils::seats_by_area(&stage, &seats, level)?;
+        for area in stage.areas_at_level(level) {
+            println!("\x1b[1m{}\x1b[0m", stage.areas[&area].name);
+            print_seats(&stage, by_area.get(&area).unwrap_or(&HashMap::new()), "  ");
+        }
+        println!();
+    }
+
+    print_seats(&stage, &utils::seats_by_party(&stage, &seats)?, "");
+
     print!("\x1b[0m\n\n");
 
     Ok(())
@@ -0,0 +1,65 @@
+extern crate vsim2;
+use vsim2::core::*;
+use vsim2::core::validate::Issue;
+use vsim2::*;
+
+use std::{env, fs, process};
+use std::path::Path;
+
+/// Reads a dataset: an `.elc` file, or a directory of JSON files, see `core::json`.
+/// Returns the results of every election in it.
+fn load(path: &Path) -> Result<(ElectionStage, Vec<ElectionResults>, Groupings), Box<dyn std::error::Error>> {
+    if path.is_dir() {
+        let (stage, results, groupings) = json::load(path)?;
+        return Ok((stage, vec![results], groupings));
+    }
+
+    let data = fs::read(path)?;
+    let mut dataset = elc::Dataset::open(&data)?;
+    let all_results = dataset.all_results()?;
+    let (stage, _, groupings) = dataset.into_data()?;
+    Ok((stage, all_results, groupings))
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let path = env::args().nth(1).ok_or("usage: vsim2-validate <data.elc | directory with JSON files>")?;
+    let (stage, all_results, groupings) = load(Path::new(&path))?;
+
+    let stage_issues = validate::validate_stage(&stage).err().unwrap_or_default();
+    let mut issues: Vec<(String, Issue)> = stage_issues.iter().map(|issue| (String::new(), issue.clone())).collect();
+    for results in all_results.iter() {
+        let prefix = if all_results.len() > 1 { format!("{}: ", results.date) } else { String::new() };
+        // Issues with the stage alone are only reported once.
+        issues.extend(validate::validate(&stage, results).err().unwrap_or_default().into_iter()
+            .filter(|issue| !stage_issues.contains(issue))
+            .map(|issue| (prefix.clone(), issue)));
+    }
+
+    let (errors, warnings): (Vec<_>, Vec<_>) = issues.into_iter().partition(|(_, issue)| issue.is_error());
+    for (prefix, issue) in warnings.iter() {
+        eprintln!("warning: {}{}", prefix, issue);
+    }
+    for (prefix, issue) in errors.iter() {
+        eprintln!("error: {}{}", prefix, issue);
+    }
+
+    let grouping_errors = match grouping::validate_all(&groupings, &stage) {
+        Ok(warnings) => {
+            for warning in warnings {
+                eprintln!("warning: {}", warning);
+            }
+            false
+        },
+        Err(e) => {
+            eprintln!("error: {}", e);
+            true
+        }
+    };
+
+    println!("{}: {} districts, {} candidates, {} elections; {} errors, {} warnings",
+        path, stage.districts.len(), stage.candidates.len(), all_results.len(), errors.len(), warnings.len());
+    if !errors.is_empty() || grouping_errors {
+        process::exit(1);
+    }
+    Ok(())
+}
@@ -0,0 +1,203 @@
+//! Building an `ElectionStage` without keeping its IDs consistent by hand.
+use super::*;
+use super::validate::Issue;
+
+/// Builds an `ElectionStage`, filling in `Area::districts`, `District::candidates`
+/// and `Area::candidates` from the districts and candidates added to it.
+#[derive(Default)]
+pub struct StageBuilder {
+    districts: HashMap<DistrictID, District>,
+    candidates: HashMap<CandidateID, Candidate>,
+    parties: HashMap<PartyID, Party>,
+    areas: HashMap<AreaID, Area>,
+    units: HashMap<UnitID, Unit>,
+    adjacency: Option<Adjacency>,
+    unit_adjacency: Option<Adjacency>,
+    /// Where each candidate stands, in the order they were added.
+    standing: Vec<(CandidateID, Option<DistrictID>, Option<AreaID>)>,
+    issues: Vec<Issue>,
+}
+
+impl ElectionStage {
+    pub fn builder() -> StageBuilder {
+        StageBuilder::default()
+    }
+}
+
+impl StageBuilder {
+    pub fn party(&mut self, id: PartyID, party: Party) -> &mut Self {
+        if self.parties.insert(id, party).is_some() {
+            self.issues.push(Issue::Duplicate(Entity::Party, id as u32));
+        }
+        self
+    }
+
+    /// Adds an area without seats of its own or population, see `area_seats` and `population`.
+    pub fn area(&mut self, id: AreaID, name: impl Into<String>, parent: Option<AreaID>) -> &mut Self {
+        let area = Area {
+            name: name.into(),
+            seats: 0,
+            population: None,
+            parent,
+            districts: HashSet::new(),
+            candidates: HashSet::new(),
+        };
+        if self.areas.insert(id, area).is_some() {
+            self.issues.push(Issue::Duplicate(Entity::Area, id as u32));
+        }
+        self
+    }
+
+    /// Sets `Area::seats` of an area added before.
+    pub fn area_seats(&mut self, id: AreaID, seats: SeatCount) -> &mut Self {
+        match self.areas.get_mut(&id) {
+            Some(area) => area.seats = seats,
+            None => self.issues.push(Issue::Unknown(Entity::Area, id as u32)),
+        }
+        self
+    }
+
+    /// Sets `Area::population` of an area added before.
+    pub fn population(&mut self, id: AreaID, population: u32) -> &mut Self {
+        match self.areas.get_mut(&id) {
+            Some(area) => area.population = Some(population),
+            None => self.issues.push(Issue::Unknown(Entity::Area, id as u32)),
+        }
+        self
+    }
+
+    pub fn district(&mut self, id: DistrictID, name: impl Into<String>, area: AreaID, seats: SeatCount) -> &mut Self {
+        let district = District { name: name.into(), seats, area, candidates: HashSet::new() };
+        if self.districts.insert(id, district).is_some() {
+            self.issues.push(Issue::Duplicate(Entity::District, id));
+        }
+        self
+    }
+
+    /// Adds a candidate standing in `district`.
+    pub fn candidate(&mut self, id: CandidateID, name: Option<String>, party: Option<PartyID>, district: DistrictID) -> &mut Self {
+        self.add_candidate(id, Candidate { name, party, election: None }, Some(district), None)
+    }
+
+    /// Adds a candidate on the list of `party` in `area`.
+    pub fn list_candidate(&mut self, id: CandidateID, name: Option<String>, party: PartyID, area: AreaID) -> &mut Self {
+        self.add_candidate(id, Candidate { name, party: Some(party), election: None }, None, Some(area))
+    }
+
+    /// Adds a candidate standing in `district` and on the list of their party in `area`, e.g. for MMP.
+    pub fn dual_candidate(&mut self, id: CandidateID, name: Option<String>, party: PartyID, district: DistrictID, area: AreaID) -> &mut Self {
+        self.add_candidate(id, Candidate { name, party: Some(party), election: None }, Some(district), Some(area))
+    }
+
+    fn add_candidate(&mut self, id: CandidateID, candidate: Candidate, district: Option<DistrictID>, area: Option<AreaID>) -> &mut Self {
+        if self.candidates.insert(id, candidate).is_some() {
+            self.issues.push(Issue::Duplicate(Entity::Candidate, id));
+        }
+        self.standing.push((id, district, area));
+        self
+    }
+
+    pub fn unit(&mut self, id: UnitID, name: impl Into<String>, district: DistrictID) -> &mut Self {
+        if self.units.insert(id, Unit { name: name.into(), district }).is_some() {
+            self.issues.push(Issue::Duplicate(Entity::Unit, id));
+        }
+        self
+    }
+
+    pub fn adjacency(&mut self, adjacency: Adjacency) -> &mut Self {
+        self.adjacency = Some(adjacency);
+        self
+    }
+
+    pub fn unit_adjacency(&mut self, adjacency: Adjacency) -> &mut Self {
+        self.unit_adjacency = Some(adjacency);
+        self
+    }
+
+    /// Links everything up and checks the stage with `validate::validate_stage`.
+    /// Fails if anything refers to an ID that wasn't added, or an ID was added twice.
+    /// Leaves the builder empty, so that it can end a chain of calls.
+    pub fn build(&mut self) -> Result<ElectionStage, Vec<Issue>> {
+        let StageBuilder { mut districts, candidates, parties, mut areas, units, adjacency, unit_adjacency, standing, mut issues } = std::mem::take(self);
+
+        let mut ids: Vec<DistrictID> = districts.keys().copied().collect();
+        ids.sort();
+        for id in ids {
+            let area = districts[&id].area;
+            if let Some(area) = areas.get_mut(&area) {
+                area.districts.insert(id);
+            }
+        }
+
+        for (candidate, district, area) in standing {
+            if let Some(id) = district {
+                match districts.get_mut(&id) {
+                    Some(district) => { district.candidates.insert(candidate); },
+                    None => issues.push(Issue::Dangling { from: (Entity::Candidate, candidate), to: (Entity::District, id) }),
+                }
+            }
+            if let Some(id) = area {
+                match areas.get_mut(&id) {
+                    Some(area) => { area.candidates.insert(candidate); },
+                    None => issues.push(Issue::Dangling { from: (Entity::Candidate, candidate), to: (Entity::Area, id as u32) }),
+                }
+            }
+        }
+
+        let stage = ElectionStage { districts, candidates, parties, areas, adjacency, units, unit_adjacency };
+        issues.extend(validate::validate_stage(&stage).err().unwrap_or_default().into_iter().filter(Issue::is_error));
+        if issues.is_empty() { Ok(stage) } else { Err(issues) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn party(name: &str) -> Party {
+        Party { name: name.to_owned(), color: 0, type_: PartyType::Other, abbreviation: None, names: HashMap::new() }
+    }
+
+    #[test]
+    fn build_links_districts_and_candidates() {
+        let mut builder = ElectionStage::builder();
+        let stage = builder.party(0, party("A"))
+            .area(1, "Country", None)
+            .area(2, "State", Some(1))
+            .district(1, "District", 2, 1)
+            .dual_candidate(5, None, 0, 1, 1)
+            .list_candidate(6, None, 0, 2)
+            .unit(1, "Unit", 1)
+            .build()
+            .unwrap();
+
+        assert!(stage.areas[&1].districts.is_empty());
+        assert_eq!(sorted::items(&stage.areas[&2].districts), vec![1]);
+        assert_eq!(sorted::items(&stage.districts[&1].candidates), vec![5]);
+        assert_eq!(sorted::items(&stage.areas[&1].candidates), vec![5]);
+        assert_eq!(sorted::items(&stage.areas[&2].candidates), vec![6]);
+        assert_eq!(stage.units_in(1), vec![1]);
+
+        // The builder is left empty.
+        assert!(builder.build().unwrap().districts.is_empty());
+    }
+
+    #[test]
+    fn build_reports_duplicate_and_unknown_ids() {
+        let issues = ElectionStage::builder()
+            .party(0, party("A"))
+            .party(0, party("B"))
+            .area(1, "Country", None)
+            .area_seats(3, 1)
+            .candidate(1, None, Some(0), 4)
+            .list_candidate(2, None, 5, 1)
+            .build()
+            .unwrap_err();
+        assert_eq!(issues, vec![
+            Issue::Duplicate(Entity::Party, 0),
+            Issue::Unknown(Entity::Area, 3),
+            Issue::Dangling { from: (Entity::Candidate, 1), to: (Entity::District, 4) },
+            Issue::Dangling { from: (Entity::Candidate, 2), to: (Entity::Party, 5) },
+        ]);
+    }
+}
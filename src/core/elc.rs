@@ -0,0 +1,508 @@
+//! The `.elc` container format.
+//!
+//! A file starts with `MAGIC`, followed by the format version and flags as
+//! little-endian `u16`s and the MessagePack-encoded `Metadata`, prefixed by
+//! its length as a little-endian `u32`.
+//!
+//! Since version 2, the rest of the file is a zip archive with one entry per
+//! section (`STAGE`, `RESULTS`, `GROUPINGS` and optionally `GEOMETRY` and
+//! results of other elections), each MessagePack-encoded and optionally
+//! deflate-compressed. Sections can be decoded on their own, see `Dataset`,
+//! and zip's CRC-32 checks each of them.
+//!
+//! In version 1, the metadata was followed by all of the data encoded together,
+//! prefixed by its length as a `u32`, and, if `FLAG_CHECKSUM` is set, the
+//! `checksum` of the data as a `u64`. Files written before the container existed
+//! (version 0) are just the encoded data. Both are migrated when read.
+use super::*;
+use serde::de::DeserializeOwned;
+use std::io::{self, Read, Write};
+
+pub const MAGIC: &[u8; 4] = b"ELC\x01";
+
+/// Version written by `write`. Increase it, and add a migration to
+/// `decode_data`, whenever the encoding of the data changes.
+pub const VERSION: u16 = 2;
+
+/// In version 1, the data is followed by its checksum.
+pub const FLAG_CHECKSUM: u16 = 1;
+
+/// Names of the sections of a file.
+pub const STAGE: &str = "stage";
+pub const RESULTS: &str = "results";
+pub const GROUPINGS: &str = "groupings";
+pub const GEOMETRY: &str = "geometry";
+/// Prefix of sections with the results of other elections,
+/// followed by the date of the election as `YYYY-MM-DD`.
+pub const OTHER_RESULTS: &str = "results/";
+
+fn other_results_section(date: Date) -> String {
+    format!("{}{}", OTHER_RESULTS, date)
+}
+
+/// Describes where a dataset comes from. Every field is optional,
+/// and unknown fields are ignored, so that it can grow without a new version.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Metadata {
+    /// E.g. `Canada`.
+    pub country: Option<String>,
+    /// E.g. `2019 federal election`.
+    pub election: Option<String>,
+    /// Where the source data was downloaded from.
+    pub source_url: Option<String>,
+    /// Name and version of the program that wrote the file, e.g. `vsim2-extract 0.1.0`.
+    pub importer: Option<String>,
+    pub created: Option<Date>,
+}
+
+/// What went wrong when reading an `.elc` file.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// The file ends before the named part.
+    Truncated(&'static str),
+    /// The file was written by a newer version of the format.
+    UnsupportedVersion { found: u16, supported: u16 },
+    /// The data doesn't match its checksum.
+    Checksum { expected: u64, found: u64 },
+    /// The named part couldn't be decoded.
+    Decode(&'static str, rmps::decode::Error),
+    /// The file has no header, and isn't a dataset written before the container existed either.
+    NotElc(rmps::decode::Error),
+    /// The sections couldn't be read, e.g. because one fails its CRC-32 check.
+    Archive(zip::result::ZipError),
+    /// The file has no section with this name.
+    MissingSection(&'static str),
+    /// The file has no results for an election on this date.
+    NoElection(Date),
+    Encode(rmps::encode::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "couldn't read dataset: {}", e),
+            Error::Truncated(part) => write!(f, "dataset is truncated: {} is missing", part),
+            Error::UnsupportedVersion { found, supported } =>
+                write!(f, "dataset is format version {}, but this version of vsim2 only reads up to version {}", found, supported),
+            Error::Checksum { expected, found } =>
+                write!(f, "dataset is corrupt: checksum is {:016x}, expected {:016x}", found, expected),
+            Error::Decode(part, e) => write!(f, "dataset is corrupt: couldn't decode {}: {}", part, e),
+            Error::NotElc(e) => write!(f, "not an .elc dataset ({})", e),
+            Error::Archive(e) => write!(f, "dataset is corrupt: {}", e),
+            Error::MissingSection(name) => write!(f, "dataset has no {} section", name),
+            Error::NoElection(date) => write!(f, "dataset has no election on {}", date),
+            Error::Encode(e) => write!(f, "couldn't encode dataset: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Decode(_, e) | Error::NotElc(e) => Some(e),
+            Error::Archive(e) => Some(e),
+            Error::Encode(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<zip::result::ZipError> for Error {
+    fn from(e: zip::result::ZipError) -> Self {
+        Error::Archive(e)
+    }
+}
+
+/// FNV-1a hash of `data`, as used by version 1.
+pub fn checksum(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+/// Everything a dataset can hold, e.g. to convert it to another format.
+#[derive(Debug, Clone)]
+pub struct Contents {
+    pub metadata: Metadata,
+    pub stage: ElectionStage,
+    pub results: ElectionResults,
+    pub groupings: Groupings,
+    pub geometry: Option<Boundaries>,
+    /// Results of other elections, ordered by date.
+    pub other_results: Vec<ElectionResults>,
+}
+
+impl Contents {
+    pub fn sections(&self) -> Sections<'_> {
+        Sections {
+            stage: &self.stage,
+            results: &self.results,
+            groupings: &self.groupings,
+            geometry: self.geometry.as_ref(),
+            other_results: &self.other_results,
+        }
+    }
+}
+
+/// The sections to write with `write_sections`.
+pub struct Sections<'a> {
+    pub stage: &'a ElectionStage,
+    pub results: &'a ElectionResults,
+    pub groupings: &'a Groupings,
+    /// District boundaries, e.g. for drawing maps.
+    pub geometry: Option<&'a Boundaries>,
+    /// Results of other elections on the same stage, see `ElectionStage::add_election`.
+    pub other_results: &'a [ElectionResults],
+}
+
+/// Writes a dataset in the current version, compressed.
+pub fn write(w: &mut (impl Write + ?Sized), metadata: &Metadata, data: (&ElectionStage, &ElectionResults, &Groupings)) -> Result<(), Error> {
+    write_sections(w, metadata, &Sections { stage: data.0, results: data.1, groupings: data.2, geometry: None, other_results: &[] }, true)
+}
+
+/// Writes a dataset in the current version, deflate-compressing each section if `compress`.
+pub fn write_sections(w: &mut (impl Write + ?Sized), metadata: &Metadata, sections: &Sections, compress: bool) -> Result<(), Error> {
+    let options = zip::write::FileOptions::default()
+        .compression_method(if compress { zip::CompressionMethod::Deflated } else { zip::CompressionMethod::Stored });
+    let mut archive = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+    add_section(&mut archive, options, STAGE, sections.stage)?;
+    add_section(&mut archive, options, RESULTS, sections.results)?;
+    add_section(&mut archive, options, GROUPINGS, &sorted::Map(sections.groupings))?;
+    if let Some(geometry) = sections.geometry {
+        add_section(&mut archive, options, GEOMETRY, &sorted::Map(geometry))?;
+    }
+    for results in sections.other_results.iter() {
+        add_section(&mut archive, options, &other_results_section(results.date), results)?;
+    }
+    let archive = archive.finish()?.into_inner();
+
+    let metadata = rmps::encode::to_vec_named(metadata).map_err(Error::Encode)?;
+    w.write_all(MAGIC)?;
+    w.write_all(&VERSION.to_le_bytes())?;
+    w.write_all(&0u16.to_le_bytes())?;
+    w.write_all(&(metadata.len() as u32).to_le_bytes())?;
+    w.write_all(&metadata)?;
+    w.write_all(&archive)?;
+    Ok(())
+}
+
+fn add_section(archive: &mut zip::ZipWriter<io::Cursor<Vec<u8>>>, options: zip::write::FileOptions, name: &str, value: &impl Serialize) -> Result<(), Error> {
+    archive.start_file(name, options)?;
+    archive.write_all(&rmps::encode::to_vec(value).map_err(Error::Encode)?)?;
+    Ok(())
+}
+
+/// Reads a whole dataset of any supported version.
+/// Files without a header get empty `Metadata`.
+pub fn read(r: &mut (impl Read + ?Sized)) -> Result<(Metadata, (ElectionStage, ElectionResults, Groupings)), Error> {
+    let mut bytes = Vec::new();
+    r.read_to_end(&mut bytes)?;
+    let dataset = Dataset::open(&bytes)?;
+    Ok((dataset.metadata().clone(), dataset.into_data()?))
+}
+
+/// A dataset whose sections are only decoded when asked for.
+/// Files older than version 2 are decoded as a whole when opened.
+pub struct Dataset<'a> {
+    metadata: Metadata,
+    version: u16,
+    storage: Storage<'a>,
+}
+
+enum Storage<'a> {
+    Sections(zip::ZipArchive<io::Cursor<&'a [u8]>>),
+    Whole(Box<(ElectionStage, ElectionResults, Groupings)>),
+}
+
+impl<'a> Dataset<'a> {
+    /// Reads the header and the list of sections.
+    pub fn open(bytes: &'a [u8]) -> Result<Dataset<'a>, Error> {
+        if !bytes.starts_with(MAGIC) {
+            let (stage, results, groupings) = decode_data(0, bytes).map_err(|e| match e {
+                Error::Decode(_, e) => Error::NotElc(e),
+                e => e
+            })?;
+            return Ok(Dataset { metadata: Metadata::default(), version: 0, storage: Storage::Whole(Box::new((stage, results, groupings))) });
+        }
+
+        let mut rest = &bytes[MAGIC.len()..];
+        let version = take_u16(&mut rest, "version")?;
+        if version > VERSION {
+            return Err(Error::UnsupportedVersion { found: version, supported: VERSION });
+        }
+        let flags = take_u16(&mut rest, "flags")?;
+
+        let length = take_u32(&mut rest, "metadata length")?;
+        let metadata = take(&mut rest, length as usize, "metadata")?;
+        let metadata: Metadata = rmps::from_read_ref(metadata).map_err(|e| Error::Decode("metadata", e))?;
+
+        if version >= 2 {
+            let archive = zip::ZipArchive::new(io::Cursor::new(rest))?;
+            return Ok(Dataset { metadata, version, storage: Storage::Sections(archive) });
+        }
+
+        let length = take_u32(&mut rest, "data length")?;
+        let data = take(&mut rest, length as usize, "data")?;
+        if flags & FLAG_CHECKSUM != 0 {
+            let mut expected = [0u8; 8];
+            expected.copy_from_slice(take(&mut rest, 8, "checksum")?);
+            let expected = u64::from_le_bytes(expected);
+            let found = checksum(data);
+            if found != expected {
+                return Err(Error::Checksum { expected, found });
+            }
+        }
+
+        let (stage, results, groupings) = decode_data(version, data)?;
+        Ok(Dataset { metadata, version, storage: Storage::Whole(Box::new((stage, results, groupings))) })
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Format version of the file.
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    /// Whether the file has the named section.
+    pub fn has(&self, name: &str) -> bool {
+        match &self.storage {
+            Storage::Sections(archive) => archive.file_names().any(|n| n == name),
+            Storage::Whole(..) => matches!(name, STAGE | RESULTS | GROUPINGS),
+        }
+    }
+
+    fn section<T: DeserializeOwned>(&mut self, name: &str) -> Result<Option<T>, Error> {
+        let archive = match &mut self.storage {
+            Storage::Sections(archive) => archive,
+            Storage::Whole(..) => return Ok(None),
+        };
+        let mut file = match archive.by_name(name) {
+            Ok(file) => file,
+            Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let mut bytes = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut bytes).map_err(|e| Error::Archive(zip::result::ZipError::Io(e)))?;
+        let part = match name {
+            STAGE => STAGE,
+            GROUPINGS => GROUPINGS,
+            GEOMETRY => GEOMETRY,
+            _ => RESULTS,
+        };
+        rmps::from_read_ref(&bytes).map(Some).map_err(|e| Error::Decode(part, e))
+    }
+
+    pub fn stage(&mut self) -> Result<ElectionStage, Error> {
+        if let Storage::Whole(whole) = &self.storage {
+            return Ok(whole.0.clone());
+        }
+        self.section(STAGE)?.ok_or(Error::MissingSection(STAGE))
+    }
+
+    pub fn results(&mut self) -> Result<ElectionResults, Error> {
+        if let Storage::Whole(whole) = &self.storage {
+            return Ok(whole.1.clone());
+        }
+        self.section(RESULTS)?.ok_or(Error::MissingSection(RESULTS))
+    }
+
+    pub fn groupings(&mut self) -> Result<Groupings, Error> {
+        if let Storage::Whole(whole) = &self.storage {
+            return Ok(whole.2.clone());
+        }
+        self.section(GROUPINGS)?.ok_or(Error::MissingSection(GROUPINGS))
+    }
+
+    /// District boundaries, if the file has them.
+    pub fn geometry(&mut self) -> Result<Option<Boundaries>, Error> {
+        self.section(GEOMETRY)
+    }
+
+    /// Results of the main election and all other elections, ordered by date.
+    pub fn all_results(&mut self) -> Result<Vec<ElectionResults>, Error> {
+        let mut all = vec![self.results()?];
+        all.extend(self.other_results()?);
+        all.sort_by_key(|r| r.date);
+        Ok(all)
+    }
+
+    /// Results of the election on `date`, which may be the main election or another one.
+    pub fn results_on(&mut self, date: Date) -> Result<ElectionResults, Error> {
+        if let Some(results) = self.section(&other_results_section(date))? {
+            return Ok(results);
+        }
+        let results = self.results()?;
+        if results.date == date { Ok(results) } else { Err(Error::NoElection(date)) }
+    }
+
+    fn other_results(&mut self) -> Result<Vec<ElectionResults>, Error> {
+        let mut names: Vec<String> = match &self.storage {
+            Storage::Sections(archive) => archive.file_names().filter(|n| n.starts_with(OTHER_RESULTS)).map(String::from).collect(),
+            Storage::Whole(..) => return Ok(Vec::new()),
+        };
+        // Dates are formatted so that this sorts them.
+        names.sort();
+        names.iter().map(|name| self.section(name).map(Option::unwrap)).collect()
+    }
+
+    /// Decodes every section.
+    pub fn into_contents(mut self) -> Result<Contents, Error> {
+        let geometry = self.geometry()?;
+        let other_results = self.other_results()?;
+        let metadata = self.metadata.clone();
+        let (stage, results, groupings) = self.into_data()?;
+        Ok(Contents { metadata, stage, results, groupings, geometry, other_results })
+    }
+
+    /// Decodes the stage, results and groupings.
+    pub fn into_data(mut self) -> Result<(ElectionStage, ElectionResults, Groupings), Error> {
+        if let Storage::Whole(whole) = self.storage {
+            return Ok(*whole);
+        }
+        Ok((self.stage()?, self.results()?, self.groupings()?))
+    }
+}
+
+fn take_u16(rest: &mut &[u8], part: &'static str) -> Result<u16, Error> {
+    take(rest, 2, part).map(|b| u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn take_u32(rest: &mut &[u8], part: &'static str) -> Result<u32, Error> {
+    take(rest, 4, part).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// Takes `n` bytes off the front of `rest`.
+fn take<'a>(rest: &mut &'a [u8], n: usize, part: &'static str) -> Result<&'a [u8], Error> {
+    if rest.len() < n {
+        return Err(Error::Truncated(part));
+    }
+    let (taken, left) = rest.split_at(n);
+    *rest = left;
+    Ok(taken)
+}
+
+/// Decodes the data of a file older than version 2, migrating it to the current structs.
+fn decode_data(version: u16, data: &[u8]) -> Result<(ElectionStage, ElectionResults, Groupings), Error> {
+    match version {
+        0 => {
+            let (stage, results, groupings): (v0::ElectionStage, ElectionResults, Groupings) =
+                rmps::from_read_ref(data).map_err(|e| Error::Decode("version 0 data", e))?;
+            Ok((stage.into(), results, groupings))
+        },
+        _ => rmps::from_read_ref(data).map_err(|e| Error::Decode("data", e)),
+    }
+}
+
+/// Structs as they were encoded in version 0, where they differ.
+/// Fields added at the end of a struct since then have defaults and need no migration.
+mod v0 {
+    use super::*;
+
+    #[derive(Deserialize)]
+    pub struct ElectionStage {
+        pub districts: HashMap<DistrictID, District>,
+        pub candidates: HashMap<CandidateID, Candidate>,
+        pub parties: HashMap<PartyID, Party>,
+        pub areas: HashMap<AreaID, Area>,
+    }
+
+    /// `population` and `parent` were added before `districts`.
+    #[derive(Deserialize)]
+    pub struct Area {
+        pub name: String,
+        pub seats: SeatCount,
+        pub districts: HashSet<DistrictID>,
+        #[serde(default)]
+        pub candidates: HashSet<CandidateID>,
+    }
+
+    impl From<ElectionStage> for super::ElectionStage {
+        fn from(stage: ElectionStage) -> Self {
+            super::ElectionStage {
+                districts: stage.districts,
+                candidates: stage.candidates,
+                parties: stage.parties,
+                areas: stage.areas.into_iter().map(|(id, area)| (id, super::Area {
+                    name: area.name,
+                    seats: area.seats,
+                    population: None,
+                    parent: None,
+                    districts: area.districts,
+                    candidates: area.candidates,
+                })).collect(),
+                adjacency: None,
+                units: HashMap::new(),
+                unit_adjacency: None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data() -> (ElectionStage, ElectionResults, Groupings) {
+        let stage = ElectionStage::builder()
+            .area(1, "Country", None)
+            .district(1, "North", 1, 1)
+            .candidate(1, None, None, 1)
+            .build()
+            .unwrap();
+        let results = ElectionResults { districts: HashMap::new(), date: Date::new(2019, 10, 21), units: HashMap::new() };
+        (stage, results, Groupings::new())
+    }
+
+    /// A version 1 file, where all the data is encoded together.
+    fn version_1(data: &(ElectionStage, ElectionResults, Groupings)) -> Vec<u8> {
+        let metadata = rmps::encode::to_vec_named(&Metadata::default()).unwrap();
+        let data = rmps::encode::to_vec(data).unwrap();
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&0u16.to_le_bytes());
+        bytes.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&metadata);
+        bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&data);
+        bytes
+    }
+
+    #[test]
+    fn whole_files_have_only_the_main_sections() {
+        let bytes = version_1(&data());
+        let dataset = Dataset::open(&bytes).unwrap();
+        assert_eq!(dataset.version(), 1);
+        for &name in [STAGE, RESULTS, GROUPINGS].iter() {
+            assert!(dataset.has(name), "{}", name);
+        }
+        for &name in [GEOMETRY, "results/2015-10-19", "nonsense"].iter() {
+            assert!(!dataset.has(name), "{}", name);
+        }
+        assert_eq!(dataset.into_data().unwrap().0.districts.len(), 1);
+    }
+
+    #[test]
+    fn sectioned_files_have_the_sections_written() {
+        let (stage, results, groupings) = data();
+        let mut bytes = Vec::new();
+        write(&mut bytes, &Metadata::default(), (&stage, &results, &groupings)).unwrap();
+        let dataset = Dataset::open(&bytes).unwrap();
+        assert_eq!(dataset.version(), VERSION);
+        for &name in [STAGE, RESULTS, GROUPINGS].iter() {
+            assert!(dataset.has(name), "{}", name);
+        }
+        for &name in [GEOMETRY, "nonsense"].iter() {
+            assert!(!dataset.has(name), "{}", name);
+        }
+    }
+}
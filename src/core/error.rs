@@ -0,0 +1,101 @@
+//! The error type shared by the methods, utilities, sources and codecs of this crate.
+use super::{elc, json};
+use std::fmt;
+
+/// Kinds of entities that can be looked up by ID in an `ElectionStage`, and
+/// columns of source data, by position.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Entity {
+    District,
+    Area,
+    Candidate,
+    Party,
+    Unit,
+    Column,
+}
+
+impl fmt::Display for Entity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Entity::District => "district",
+            Entity::Area => "area",
+            Entity::Candidate => "candidate",
+            Entity::Party => "party",
+            Entity::Unit => "unit",
+            Entity::Column => "column",
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// An ID that isn't in the stage.
+    Missing(Entity, u32),
+    /// The data contradicts itself, e.g. results for a district have no entry.
+    Inconsistent(String),
+    /// A method can't run on this data, e.g. because a grouping it needs is missing.
+    Method(String),
+    /// A value couldn't be parsed, e.g. a date.
+    Parse(String),
+    /// Reading or writing a dataset failed.
+    Codec(elc::Error),
+    /// Reading a JSON dataset failed.
+    Json(json::Error),
+    /// Reading source data failed.
+    Source(Box<dyn std::error::Error>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Missing(entity, id) => write!(f, "unknown {} {}", entity, id),
+            Error::Inconsistent(msg) => write!(f, "inconsistent data: {}", msg),
+            Error::Method(msg) => f.write_str(msg),
+            Error::Parse(msg) => f.write_str(msg),
+            Error::Codec(e) => fmt::Display::fmt(e, f),
+            Error::Json(e) => fmt::Display::fmt(e, f),
+            Error::Source(e) => write!(f, "couldn't read source data: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Codec(e) => Some(e),
+            Error::Json(e) => Some(e),
+            Error::Source(e) => Some(e.as_ref()),
+            _ => None
+        }
+    }
+}
+
+impl From<elc::Error> for Error {
+    fn from(e: elc::Error) -> Self {
+        Error::Codec(e)
+    }
+}
+
+impl From<json::Error> for Error {
+    fn from(e: json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+impl From<csv::Error> for Error {
+    fn from(e: csv::Error) -> Self {
+        Error::Source(Box::new(e))
+    }
+}
+
+impl From<std::num::ParseIntError> for Error {
+    fn from(e: std::num::ParseIntError) -> Self {
+        Error::Source(Box::new(e))
+    }
+}
+
+impl From<zip::result::ZipError> for Error {
+    fn from(e: zip::result::ZipError) -> Self {
+        Error::Source(Box::new(e))
+    }
+}
@@ -0,0 +1,136 @@
+//! The JSON dataset format: `stage.json`, `results.json` and `groupings.json`
+//! in one directory, as written by `extract`, and JSON Schemas describing them,
+//! so that datasets can be written by other tools. The directory may also hold
+//! `metadata.json`, `boundaries.json` and the results of other elections.
+use super::*;
+use schemars::schema::RootSchema;
+use serde::de::DeserializeOwned;
+use std::io;
+use std::path::Path;
+
+pub const STAGE_FILE: &str = "stage.json";
+pub const RESULTS_FILE: &str = "results.json";
+pub const GROUPINGS_FILE: &str = "groupings.json";
+pub const METADATA_FILE: &str = "metadata.json";
+pub const BOUNDARIES_FILE: &str = "boundaries.json";
+
+/// File with the results of another election, see `elc::Sections::other_results`.
+pub fn other_results_file(date: Date) -> String {
+    format!("results-{}.json", date)
+}
+
+/// JSON Schemas of the three files, keyed by the name they're published under in `schema/`.
+pub fn schemas() -> Vec<(&'static str, RootSchema)> {
+    vec![
+        ("stage.schema.json", schemars::schema_for!(ElectionStage)),
+        ("results.schema.json", schemars::schema_for!(ElectionResults)),
+        ("groupings.schema.json", schemars::schema_for!(Groupings)),
+    ]
+}
+
+pub enum Error {
+    Io(String, io::Error),
+    /// The file isn't valid JSON, or the value at `path` doesn't match its schema.
+    /// `path` is empty for errors at the top level.
+    Parse { file: String, path: String, error: serde_json::Error },
+}
+
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(file, e) => write!(f, "couldn't read {}: {}", file, e),
+            Error::Parse { file, path, error } if path.is_empty() || path == "." => write!(f, "{}: {}", file, error),
+            Error::Parse { file, path, error } => write!(f, "{}: at {}: {}", file, path, error),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(_, e) => Some(e),
+            Error::Parse { error, .. } => Some(error),
+        }
+    }
+}
+
+/// Reads one file, named `file` in errors. The error names the path of the value
+/// that couldn't be read, e.g. `districts.35001.seats`, along with its line and column.
+pub fn from_reader<T: DeserializeOwned>(file: &str, reader: impl io::Read) -> Result<T, Error> {
+    let mut de = serde_json::Deserializer::from_reader(reader);
+    let parse_error = |path: String, error| Error::Parse { file: file.to_owned(), path, error };
+    let value = serde_path_to_error::deserialize(&mut de)
+        .map_err(|e| parse_error(e.path().to_string(), e.into_inner()))?;
+    de.end().map_err(|e| parse_error(String::new(), e))?;
+    Ok(value)
+}
+
+fn read_file<T: DeserializeOwned>(dir: &Path, name: &str) -> Result<T, Error> {
+    let path = dir.join(name);
+    let file = std::fs::File::open(&path).map_err(|e| Error::Io(path.display().to_string(), e))?;
+    from_reader(&path.display().to_string(), io::BufReader::new(file))
+}
+
+fn read_optional<T: DeserializeOwned>(dir: &Path, name: &str) -> Result<Option<T>, Error> {
+    if dir.join(name).exists() { read_file(dir, name).map(Some) } else { Ok(None) }
+}
+
+/// Reads a dataset from the JSON files in `dir`. `groupings.json` is optional.
+pub fn load(dir: &Path) -> Result<(ElectionStage, ElectionResults, Groupings), Error> {
+    let stage = read_file(dir, STAGE_FILE)?;
+    let results = read_file(dir, RESULTS_FILE)?;
+    let groupings = read_optional(dir, GROUPINGS_FILE)?.unwrap_or_default();
+    Ok((stage, results, groupings))
+}
+
+/// Like `load`, but also reads the optional files.
+pub fn load_contents(dir: &Path) -> Result<elc::Contents, Error> {
+    let (stage, results, groupings) = load(dir)?;
+    let entries = std::fs::read_dir(dir).map_err(|e| Error::Io(dir.display().to_string(), e))?;
+    let mut names = Vec::new();
+    for entry in entries {
+        let name = entry.map_err(|e| Error::Io(dir.display().to_string(), e))?.file_name().to_string_lossy().into_owned();
+        if name.starts_with("results-") && name.ends_with(".json") {
+            names.push(name);
+        }
+    }
+    names.sort();
+    Ok(elc::Contents {
+        metadata: read_optional(dir, METADATA_FILE)?.unwrap_or_default(),
+        stage,
+        results,
+        groupings,
+        geometry: read_optional(dir, BOUNDARIES_FILE)?,
+        other_results: names.iter().map(|name| read_file(dir, name)).collect::<Result<_, _>>()?,
+    })
+}
+
+fn write_file(dir: &Path, name: &str, value: &impl Serialize) -> Result<(), Error> {
+    let path = dir.join(name);
+    let io_error = |e| Error::Io(path.display().to_string(), e);
+    let file = std::fs::File::create(&path).map_err(io_error)?;
+    serde_json::to_writer_pretty(io::BufWriter::new(file), value).map_err(|e| io_error(e.into()))
+}
+
+/// Writes a dataset to `dir`, creating it if needed. Boundaries and other
+/// elections are only written if the dataset has them.
+pub fn save(dir: &Path, metadata: &elc::Metadata, sections: &elc::Sections) -> Result<(), Error> {
+    std::fs::create_dir_all(dir).map_err(|e| Error::Io(dir.display().to_string(), e))?;
+    write_file(dir, STAGE_FILE, sections.stage)?;
+    write_file(dir, RESULTS_FILE, sections.results)?;
+    write_file(dir, GROUPINGS_FILE, &sorted::Map(sections.groupings))?;
+    write_file(dir, METADATA_FILE, metadata)?;
+    if let Some(geometry) = sections.geometry {
+        write_file(dir, BOUNDARIES_FILE, &sorted::Map(geometry))?;
+    }
+    for results in sections.other_results.iter() {
+        write_file(dir, &other_results_file(results.date), results)?;
+    }
+    Ok(())
+}
@@ -1,13 +1,26 @@
 use std::any::Any;
 use std::collections::{HashMap,HashSet};
 use serde::{Serialize,Deserialize,Serializer,Deserializer};
+use schemars::JsonSchema;
 use serde::ser::{SerializeTuple};
 use serde::de::{self, Visitor};
 use std::fmt;
 
 extern crate rmp_serde as rmps;
 
-#[derive(Debug, Copy, Clone)]
+pub mod elc;
+pub mod error;
+pub mod json;
+pub mod parties;
+pub mod tidy;
+pub mod validate;
+mod builder;
+
+pub use error::{Error, Entity};
+pub use builder::StageBuilder;
+
+/// A calendar date. Ordered chronologically; formatted and parsed as ISO 8601 (`YYYY-MM-DD`).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Date {
     pub year: u32,
     pub month: u8,
@@ -18,6 +31,76 @@ impl Date {
     pub fn new(year: u32, month: u8, day: u8) -> Date {
         Date { year, month, day }
     }
+
+    /// Like `new`, but fails if the month or day don't exist.
+    pub fn checked(year: u32, month: u8, day: u8) -> Result<Date, Error> {
+        let date = Date { year, month, day };
+        date.validate()?;
+        Ok(date)
+    }
+
+    /// Number of days in `month` of `year`, or 0 if there's no such month.
+    pub fn days_in_month(year: u32, month: u8) -> u8 {
+        match month {
+            1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+            4 | 6 | 9 | 11 => 30,
+            2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => 29,
+            2 => 28,
+            _ => 0
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.month < 1 || self.month > 12 {
+            return Err(Error::Inconsistent(format!("invalid month {} in date {}", self.month, self)));
+        }
+        if self.day < 1 || self.day > Date::days_in_month(self.year, self.month) {
+            return Err(Error::Inconsistent(format!("invalid day {} in date {}", self.day, self)));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+impl std::str::FromStr for Date {
+    type Err = Error;
+
+    /// Parses an ISO 8601 calendar date, `YYYY-MM-DD`.
+    fn from_str(s: &str) -> Result<Date, Error> {
+        let error = || Error::Parse(format!("invalid date {:?}, expected YYYY-MM-DD", s));
+        let mut parts = s.trim().splitn(3, '-');
+        let mut next = |len: usize| parts.next().filter(|p| p.len() == len && p.bytes().all(|b| b.is_ascii_digit())).ok_or_else(error);
+        let year = next(4)?.parse().map_err(|_| error())?;
+        let month = next(2)?.parse().map_err(|_| error())?;
+        let day = next(2)?.parse().map_err(|_| error())?;
+        Date::checked(year, month, day).map_err(|_| error())
+    }
+}
+
+impl JsonSchema for Date {
+    fn schema_name() -> String {
+        "Date".to_owned()
+    }
+
+    /// `[year, month, day]`, as written by `Serialize`.
+    fn json_schema(_: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        serde_json::from_value(serde_json::json!({
+            "description": "[year, month, day]",
+            "type": "array",
+            "items": [
+                { "type": "integer", "minimum": 0 },
+                { "type": "integer", "minimum": 1, "maximum": 12 },
+                { "type": "integer", "minimum": 1, "maximum": 31 }
+            ],
+            "minItems": 3,
+            "maxItems": 3
+        })).unwrap()
+    }
 }
 
 impl Serialize for Date {
@@ -46,7 +129,7 @@ impl<'de> Deserialize<'de> for Date {
                 let year = seq.next_element::<u32>()?.ok_or(<A::Error as de::Error>::custom("year value not present"))?;
                 let month = seq.next_element::<u8>()?.ok_or(<A::Error as de::Error>::custom("month value not present"))?;
                 let day = seq.next_element::<u8>()?.ok_or(<A::Error as de::Error>::custom("day value not present"))?;
-                Ok(Date{year, month, day})
+                Date::checked(year, month, day).map_err(<A::Error as de::Error>::custom)
             }
         }
 
@@ -58,22 +141,178 @@ pub type DistrictID = u32;
 pub type AreaID = u16;
 pub type CandidateID = u32;
 pub type PartyID = u8;
+pub type UnitID = u32;
 
 pub type SeatCount = u16;
 
 /// Represents data before the election.
-#[derive(Debug,Clone, Serialize,Deserialize)]
+#[derive(Debug,Clone, Serialize,Deserialize, JsonSchema)]
 pub struct ElectionStage {
+    #[serde(serialize_with = "sorted::map")]
     pub districts: HashMap<DistrictID, District>,
+    #[serde(serialize_with = "sorted::map")]
     pub candidates: HashMap<CandidateID, Candidate>,
+    #[serde(serialize_with = "sorted::map")]
     pub parties: HashMap<PartyID, Party>,
+    #[serde(serialize_with = "sorted::map")]
     pub areas: HashMap<AreaID, Area>,
+
+    /// Which districts border each other, if known.
+    #[serde(default)]
+    pub adjacency: Option<Adjacency>,
+
+    /// Sub-district units, like polling divisions or municipalities, if known.
+    #[serde(default)]
+    #[serde(serialize_with = "sorted::map")]
+    pub units: HashMap<UnitID, Unit>,
+
+    /// Which units border each other, if known. Keyed by `UnitID`.
+    #[serde(default)]
+    pub unit_adjacency: Option<Adjacency>,
+}
+
+impl ElectionStage {
+    pub fn district(&self, id: DistrictID) -> Result<&District, Error> {
+        self.districts.get(&id).ok_or(Error::Missing(Entity::District, id))
+    }
+
+    pub fn area(&self, id: AreaID) -> Result<&Area, Error> {
+        self.areas.get(&id).ok_or(Error::Missing(Entity::Area, id as u32))
+    }
+
+    pub fn candidate(&self, id: CandidateID) -> Result<&Candidate, Error> {
+        self.candidates.get(&id).ok_or(Error::Missing(Entity::Candidate, id))
+    }
+
+    pub fn party(&self, id: PartyID) -> Result<&Party, Error> {
+        self.parties.get(&id).ok_or(Error::Missing(Entity::Party, id as u32))
+    }
+
+    /// `area` followed by its parent, its parent's parent, and so on.
+    pub fn ancestors(&self, area: AreaID) -> Vec<AreaID> {
+        let mut ancestors = vec![area];
+        while let Some(parent) = self.areas.get(ancestors.last().unwrap()).and_then(|a| a.parent) {
+            if ancestors.contains(&parent) {
+                break
+            }
+            ancestors.push(parent);
+        }
+        ancestors
+    }
+
+    /// Depth of `area` in the hierarchy. Top-level areas are at level 0.
+    pub fn area_level(&self, area: AreaID) -> usize {
+        self.ancestors(area).len() - 1
+    }
+
+    /// Number of levels in the hierarchy. `1` if no area has a parent.
+    pub fn levels(&self) -> usize {
+        self.areas.keys().map(|&area| self.area_level(area) + 1).max().unwrap_or(1)
+    }
+
+    /// The area containing `area` at `level`, or `area` itself
+    /// if it is at or above `level`.
+    pub fn area_at_level(&self, area: AreaID, level: usize) -> AreaID {
+        let ancestors = self.ancestors(area);
+        if ancestors.len() > level { ancestors[ancestors.len() - 1 - level] } else { area }
+    }
+
+    /// Areas to use when working at `level`: the areas at that level, and areas
+    /// without children above it, so that every district is in exactly one of them.
+    pub fn areas_at_level(&self, level: usize) -> Vec<AreaID> {
+        let parents: HashSet<AreaID> = self.areas.values().filter_map(|a| a.parent).collect();
+        let mut areas: Vec<AreaID> = self.areas.keys().copied()
+            .filter(|&area| {
+                let area_level = self.area_level(area);
+                area_level == level || (area_level < level && !parents.contains(&area))
+            })
+            .collect();
+        areas.sort();
+        areas
+    }
+
+    /// Districts in `area` and all areas inside it.
+    pub fn districts_in(&self, area: AreaID) -> HashSet<DistrictID> {
+        self.areas.iter()
+            .filter(|(&id, _)| self.ancestors(id).contains(&area))
+            .flat_map(|(_, a)| a.districts.iter().copied())
+            .collect()
+    }
+
+    /// Adds the parties and candidates of `stage`, another import with the same
+    /// districts and areas, e.g. of an earlier election, to this stage. Returns
+    /// `results` with IDs changed to match this stage, so that both elections can be run on it.
+    ///
+    /// Parties are matched by name. Candidates are always added as new candidates,
+    /// standing only in the election of `results`. Candidates of this stage that
+    /// stood in every election are set to stand only in its own, held on `date`.
+    /// Results for different districts should be transposed onto these first,
+    /// see `redistricting::transposition`.
+    pub fn add_election(&mut self, date: Date, stage: &ElectionStage, results: &ElectionResults) -> Result<ElectionResults, Error> {
+        let mut party_ids: Vec<PartyID> = stage.parties.keys().copied().collect();
+        party_ids.sort();
+        let mut parties: HashMap<PartyID, PartyID> = HashMap::with_capacity(party_ids.len());
+        for id in party_ids {
+            let party = &stage.parties[&id];
+            let new = match self.parties.iter().find(|(_, p)| p.name == party.name) {
+                Some((&new, _)) => new,
+                None => {
+                    let new = (0..=PartyID::MAX).find(|id| !self.parties.contains_key(id)).ok_or(Error::Inconsistent("too many parties".into()))?;
+                    self.parties.insert(new, party.clone());
+                    new
+                }
+            };
+            parties.insert(id, new);
+        }
+
+        let mut candidate_ids: Vec<CandidateID> = stage.candidates.keys().copied().collect();
+        candidate_ids.sort();
+        for candidate in self.candidates.values_mut() {
+            candidate.election.get_or_insert(date);
+        }
+        let first = self.candidates.keys().max().map(|&x| x + 1).unwrap_or(0);
+        let candidates: HashMap<CandidateID, CandidateID> = candidate_ids.iter().enumerate().map(|(i, &id)| (id, first + i as CandidateID)).collect();
+        for (id, candidate) in stage.candidates.iter() {
+            self.candidates.insert(candidates[id], Candidate {
+                name: candidate.name.clone(),
+                party: candidate.party.map(|p| parties[&p]),
+                election: candidate.election.or(Some(results.date)),
+            });
+        }
+
+        for (id, district) in stage.districts.iter() {
+            let own = self.districts.get_mut(id).ok_or(Error::Missing(Entity::District, *id))?;
+            own.candidates.extend(district.candidates.iter().map(|c| candidates[c]));
+        }
+        for (id, area) in stage.areas.iter() {
+            let own = self.areas.get_mut(id).ok_or(Error::Missing(Entity::Area, *id as u32))?;
+            own.candidates.extend(area.candidates.iter().map(|c| candidates[c]));
+        }
+
+        let map = |r: &DistrictResults| DistrictResults {
+            party_votes: r.party_votes.iter().map(|(p, &v)| (parties[p], v)).collect(),
+            candidate_votes: r.candidate_votes.iter().map(|(c, &v)| (candidates[c], v)).collect(),
+            ..r.clone()
+        };
+        Ok(ElectionResults {
+            districts: results.districts.iter().map(|(&id, r)| (id, map(r))).collect(),
+            date: results.date,
+            units: results.units.iter().map(|(&id, r)| (id, map(r))).collect(),
+        })
+    }
+
+    /// Units in `district`, in ID order.
+    pub fn units_in(&self, district: DistrictID) -> Vec<UnitID> {
+        let mut units: Vec<UnitID> = self.units.iter().filter(|(_, u)| u.district == district).map(|(&id, _)| id).collect();
+        units.sort();
+        units
+    }
 }
 
 /// Represents a set of districts, like a province or state.
 /// If the country does not have electorally-relevant provinces or similar,
 /// use one `Area` for the entire country.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Area {
     pub name: String,
 
@@ -82,7 +321,19 @@ pub struct Area {
     /// electoral district. If none, should be `0`.
     pub seats: SeatCount,
 
+    /// Population or electorate of this `Area`, used for apportionment.
+    /// `None` if not available.
+    #[serde(default)]
+    pub population: Option<u32>,
+
+    /// `Area` containing this one, e.g. the country containing a state.
+    /// `None` for top-level areas. `District`s normally belong to the
+    /// innermost areas.
+    #[serde(default)]
+    pub parent: Option<AreaID>,
+
     /// `District`s inside of this `Area`
+    #[serde(serialize_with = "sorted::set")]
     pub districts: HashSet<DistrictID>,
 
     /// Candidates associated with the given area.
@@ -90,11 +341,12 @@ pub struct Area {
     /// or `District::candidates`.
     #[serde(skip_serializing_if = "HashSet::is_empty")]
     #[serde(default)]
+    #[serde(serialize_with = "sorted::set")]
     pub candidates: HashSet<CandidateID>,
 }
 
 /// Represents an electoral district.
-#[derive(Debug,Clone,Serialize,Deserialize)]
+#[derive(Debug,Clone,Serialize,Deserialize, JsonSchema)]
 pub struct District {
     pub name: String,
     pub seats: SeatCount,
@@ -105,9 +357,18 @@ pub struct District {
     /// or `District::candidates`.
     #[serde(skip_serializing_if = "HashSet::is_empty")]
     #[serde(default)]
+    #[serde(serialize_with = "sorted::set")]
     pub candidates: HashSet<CandidateID>,
 }
 
+/// Represents a part of a `District` that results are reported for,
+/// like a polling division or a municipality.
+#[derive(Debug,Clone,Serialize,Deserialize, JsonSchema)]
+pub struct Unit {
+    pub name: String,
+    pub district: DistrictID,
+}
+
 /// Represents a candidate.
 ///
 /// Candidates running in district-wide elections (e.g. on a local party list,
@@ -116,7 +377,7 @@ pub struct District {
 /// should be put in `Area::district`. Candidates that run in both kinds of
 /// elections (e.g. for MMP - regional party list + local election)
 /// should be put in both `District::candidates` and `Area::candidates`.
-#[derive(Debug,Clone,Serialize,Deserialize)]
+#[derive(Debug,Clone,Serialize,Deserialize, JsonSchema)]
 pub struct Candidate {
     /// Name of the candidate.
     /// `None` if the name data isn't available.
@@ -125,18 +386,43 @@ pub struct Candidate {
     /// Party of the candidate.
     /// `None` if the candidate is an independent.
     pub party: Option<PartyID>,
+
+    /// Date of the election the candidate stands in, for stages with more than
+    /// one, see `ElectionStage::add_election`. `None` if they stand in all of them.
+    #[serde(default)]
+    pub election: Option<Date>,
 }
 
-#[derive(Debug,Clone,Serialize,Deserialize)]
+impl Candidate {
+    /// Whether the candidate stands in the election on `date`.
+    pub fn stands_in(&self, date: Date) -> bool {
+        self.election.is_none() || self.election == Some(date)
+    }
+}
+
+#[derive(Debug,Clone,Serialize,Deserialize, JsonSchema)]
 pub struct Party {
     pub name: String,
     #[serde(rename = "type")]
     pub type_: PartyType,
     pub color: u32,
+    /// E.g. `SPD`, for narrow places. Filled in from `parties::Registry` by the importers.
+    #[serde(default)]
+    pub abbreviation: Option<String>,
+    /// Names by language code, e.g. `de`, from `parties::Registry`.
+    #[serde(default, serialize_with = "sorted::map")]
+    pub names: HashMap<String, String>,
+}
+
+impl Party {
+    /// The name in `language`, or `name`.
+    pub fn name_in(&self, language: &str) -> &str {
+        self.names.get(language).unwrap_or(&self.name)
+    }
 }
 
 /// Represents the category of a party.
-#[derive(Debug,Copy, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug,Copy, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq, PartialOrd, Ord)]
 #[serde(rename_all = "kebab-case")]
 pub enum PartyType {
     Left = 0,
@@ -154,16 +440,96 @@ impl Default for PartyType {
     }
 }
 
+/// Districts that border each other, with the length of the shared boundary if known.
+/// Every edge is stored in both directions. Also used for `Unit`s.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(transparent)]
+pub struct Adjacency(#[serde(serialize_with = "sorted::nested_map")] pub HashMap<DistrictID, HashMap<DistrictID, Option<f64>>>);
+
+impl Adjacency {
+    pub fn new() -> Adjacency {
+        Adjacency(HashMap::new())
+    }
+
+    /// Adds an edge between `a` and `b`. If the edge already exists,
+    /// the boundary lengths are added together.
+    pub fn add(&mut self, a: DistrictID, b: DistrictID, length: Option<f64>) {
+        if a == b {
+            return;
+        }
+        for &(from, to) in [(a, b), (b, a)].iter() {
+            let entry = self.0.entry(from).or_default().entry(to).or_insert(None);
+            *entry = match (*entry, length) {
+                (Some(old), Some(new)) => Some(old + new),
+                (old, new) => old.or(new),
+            };
+        }
+    }
+
+    pub fn neighbours<'a>(&'a self, district: DistrictID) -> impl Iterator<Item=DistrictID> + 'a {
+        self.0.get(&district).into_iter().flat_map(|n| n.keys().copied())
+    }
+
+    pub fn are_adjacent(&self, a: DistrictID, b: DistrictID) -> bool {
+        self.0.get(&a).map(|n| n.contains_key(&b)).unwrap_or(false)
+    }
+
+    /// Length of the boundary shared by `a` and `b`, if they are adjacent and it is known.
+    pub fn boundary_length(&self, a: DistrictID, b: DistrictID) -> Option<f64> {
+        self.0.get(&a).and_then(|n| n.get(&b)).and_then(|&length| length)
+    }
+
+    /// Orders `districts` so that neighbours tend to be next to each other,
+    /// by walking the graph breadth-first from the lowest ID.
+    pub fn order(&self, districts: &HashSet<DistrictID>) -> Vec<DistrictID> {
+        let mut sorted: Vec<DistrictID> = districts.iter().copied().collect();
+        sorted.sort();
+
+        let mut visited = HashSet::with_capacity(sorted.len());
+        let mut order = Vec::with_capacity(sorted.len());
+        for &start in sorted.iter() {
+            if !visited.insert(start) {
+                continue
+            }
+
+            let mut queue = std::collections::VecDeque::new();
+            queue.push_back(start);
+            while let Some(district) = queue.pop_front() {
+                order.push(district);
+
+                let mut neighbours: Vec<DistrictID> = self.neighbours(district).filter(|d| districts.contains(d)).collect();
+                neighbours.sort();
+                for neighbour in neighbours {
+                    if visited.insert(neighbour) {
+                        queue.push_back(neighbour);
+                    }
+                }
+            }
+        }
+        order
+    }
+}
+
+/// A closed ring of `(x, y)` points.
+pub type Ring = Vec<(f64, f64)>;
+
+/// Boundaries of each district, made of one or more rings.
+pub type Boundaries = HashMap<DistrictID, Vec<Ring>>;
+
 /// Format: `[set of districts]`
 #[repr(transparent)]
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(transparent)]
-pub struct Grouping (pub Vec<HashSet<DistrictID>>);
+pub struct Grouping (#[serde(serialize_with = "sorted::sets")] pub Vec<HashSet<DistrictID>>);
 
 impl Grouping {
-    pub fn candidates(&self, grouping: usize, stage: &ElectionStage) -> HashSet<CandidateID> {
-        let districts = &self.0[grouping];
-        districts.iter().map(|&district| stage.districts[&district].candidates.iter()).flatten().map(|&x| x).collect()
+    pub fn candidates(&self, grouping: usize, stage: &ElectionStage) -> Result<HashSet<CandidateID>, Error> {
+        let districts = self.0.get(grouping).ok_or(Error::Method(format!("group {} does not exist", grouping)))?;
+        let mut candidates = HashSet::new();
+        for &district in districts.iter() {
+            candidates.extend(stage.district(district)?.candidates.iter().copied());
+        }
+        Ok(candidates)
     }
 
     pub fn keys(&self) -> impl Iterator<Item=usize> {
@@ -179,23 +545,121 @@ impl Grouping {
     }
 }
 
+/// Groupings keyed by their target group size. Individual groups
+/// may deviate from the target, e.g. when the number of districts
+/// in an `Area` is not divisible by it.
 pub type Groupings = HashMap<u32, Grouping>;
 
+/// Describes a `Grouping` needed by an `ElectoralMethod`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GroupingSize {
+    /// Every group contains exactly this many districts.
+    Exact(u32),
+    /// Every group contains between `min` and `max` districts (inclusive),
+    /// e.g. for STV with variable district magnitudes.
+    Range { min: u32, max: u32 },
+}
+
+impl GroupingSize {
+    /// Size to look up first in `Groupings`.
+    pub fn preferred(&self) -> u32 {
+        match *self {
+            GroupingSize::Exact(size) => size,
+            GroupingSize::Range { min, max } => (min + max) / 2,
+        }
+    }
+
+    /// Whether every group in `grouping` has an acceptable size.
+    /// Each `Area` may have one smaller group holding the districts left over
+    /// when its district count doesn't divide evenly.
+    pub fn matches(&self, grouping: &Grouping, stage: &ElectionStage) -> bool {
+        let (min, max) = match *self {
+            GroupingSize::Exact(size) => (size, size),
+            GroupingSize::Range { min, max } => (min, max),
+        };
+
+        let mut remainders: HashSet<AreaID> = HashSet::new();
+        grouping.values().all(|group| {
+            let size = group.len() as u32;
+            if size > max {
+                return false;
+            }
+            if size >= min {
+                return true;
+            }
+
+            match group.iter().next().and_then(|id| stage.districts.get(id)) {
+                Some(district) => remainders.insert(district.area),
+                None => false
+            }
+        })
+    }
+}
+
+impl fmt::Display for GroupingSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GroupingSize::Exact(size) => write!(f, "{}", size),
+            GroupingSize::Range { min, max } => write!(f, "{}-{}", min, max),
+        }
+    }
+}
+
+/// Finds one grouping for each of `sizes`, in the same order.
+///
+/// A grouping stored under `GroupingSize::preferred` is tried first; otherwise
+/// any grouping whose groups all match is used. Returns an error naming the
+/// size that could not be satisfied instead of falling back to a mismatched grouping.
+pub fn select_groupings<'a>(stage: &ElectionStage, groupings: &'a Groupings, sizes: &[GroupingSize]) -> Result<Vec<&'a Grouping>, Error> {
+    sizes.iter().map(|size| {
+        if let Some(grouping) = groupings.get(&size.preferred()).filter(|g| size.matches(g, stage)) {
+            return Ok(grouping);
+        }
+
+        let mut keys: Vec<u32> = groupings.keys().copied().collect();
+        keys.sort();
+        keys.iter().map(|k| &groupings[k]).find(|g| size.matches(g, stage))
+            .ok_or(Error::Method(format!("dataset has no grouping with groups of size {}", size)))
+    }).collect()
+}
+
 //= Data after the election =//
 
 /// Represents the election results.
-#[derive(Debug,Clone,Serialize,Deserialize)]
+#[derive(Debug,Clone,Serialize,Deserialize, JsonSchema)]
 pub struct ElectionResults {
+    #[serde(serialize_with = "sorted::map")]
     pub districts: HashMap<DistrictID, DistrictResults>,
     pub date: Date,
+
+    /// Results in each `Unit`, if known. The results of a district should
+    /// add up to those of its units.
+    #[serde(default)]
+    #[serde(serialize_with = "sorted::map")]
+    pub units: HashMap<UnitID, DistrictResults>,
+}
+
+impl ElectionResults {
+    /// Adds up the results of `units`, e.g. to get the results of a
+    /// district drawn from them. `None` if none of them have results.
+    pub fn sum_units<'a>(&self, units: impl IntoIterator<Item = &'a UnitID>) -> Option<DistrictResults> {
+        let mut total: Option<DistrictResults> = None;
+        for results in units.into_iter().filter_map(|unit| self.units.get(unit)) {
+            match &mut total {
+                Some(total) => total.add(results),
+                None => total = Some(results.clone()),
+            }
+        }
+        total
+    }
 }
 
-#[derive(Debug,Clone,Serialize,Deserialize,PartialEq, Eq, Hash, Copy)]
+#[derive(Debug,Clone,Serialize,Deserialize, JsonSchema,PartialEq, Eq, Hash, Copy)]
 #[serde(rename_all = "kebab-case")]
 pub enum PartyListSource { Area, District }
 
 /// Represents results in one electoral district.
-#[derive(Debug,Clone,Serialize,Deserialize)]
+#[derive(Debug,Clone,Serialize,Deserialize, JsonSchema)]
 pub struct DistrictResults {
     /// Votes for a party. This should
     /// only be used in closed party-list PR
@@ -203,10 +667,56 @@ pub struct DistrictResults {
     /// votes only for a party. In open-list
     /// elections, `candidate_votes` should be
     /// used instead.
+    #[serde(serialize_with = "sorted::map")]
     pub party_votes: HashMap<PartyID, u32>,
     pub party_list_source: PartyListSource,
 
+    #[serde(serialize_with = "sorted::map")]
     pub candidate_votes: HashMap<CandidateID, u32>,
+
+    /// Number of registered voters, if known.
+    #[serde(default)]
+    pub electorate: Option<u32>,
+
+    /// Number of ballots cast, including invalid ones, if known.
+    #[serde(default)]
+    pub ballots: Option<u32>,
+
+    /// Number of invalid or blank ballots, if known. Where voters cast
+    /// separate candidate and party votes, this counts candidate votes.
+    #[serde(default)]
+    pub invalid: Option<u32>,
+
+    /// Number of invalid or blank party votes, where those are counted separately.
+    #[serde(default)]
+    pub invalid_party: Option<u32>,
+}
+
+impl DistrictResults {
+    /// Adds `other`'s votes to these results. Counts like `electorate`
+    /// stay known only if they are known for both.
+    pub fn add(&mut self, other: &DistrictResults) {
+        for (&party, &votes) in other.party_votes.iter() {
+            *self.party_votes.entry(party).or_insert(0) += votes;
+        }
+        for (&candidate, &votes) in other.candidate_votes.iter() {
+            *self.candidate_votes.entry(candidate).or_insert(0) += votes;
+        }
+
+        let add = |a: Option<u32>, b: Option<u32>| a.and_then(|a| b.map(|b| a + b));
+        self.electorate = add(self.electorate, other.electorate);
+        self.ballots = add(self.ballots, other.ballots);
+        self.invalid = add(self.invalid, other.invalid);
+        self.invalid_party = add(self.invalid_party, other.invalid_party);
+    }
+
+    /// Share of the electorate that cast a ballot.
+    pub fn turnout(&self) -> Option<f64> {
+        match (self.ballots, self.electorate) {
+            (Some(ballots), Some(electorate)) if electorate > 0 => Some(ballots as f64 / electorate as f64),
+            _ => None
+        }
+    }
 }
 
 //= Data after voting method =//
@@ -214,66 +724,372 @@ pub struct DistrictResults {
 pub struct SeatResult {
     pub seats: HashSet<CandidateID>,
 }
+
+/// Type and accepted range of a method parameter.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum ParamType {
+    Float { min: f64, max: f64 },
+    Int { min: i64, max: i64 },
+    Bool,
+}
+
+impl fmt::Display for ParamType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParamType::Float { min, max } => write!(f, "a number between {} and {}", min, max),
+            ParamType::Int { min, max } => write!(f, "an integer between {} and {}", min, max),
+            ParamType::Bool => write!(f, "true or false"),
+        }
+    }
+}
+
+/// Value of a method parameter.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ParamValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+}
+
+impl ParamValue {
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            ParamValue::Float(v) => Some(v),
+            ParamValue::Int(v) => Some(v as f64),
+            ParamValue::Bool(_) => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            ParamValue::Int(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            ParamValue::Bool(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ParamValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParamValue::Bool(v) => write!(f, "{}", v),
+            ParamValue::Int(v) => write!(f, "{}", v),
+            ParamValue::Float(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+/// Describes a parameter of an `ElectoralMethod`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ParamSpec {
+    pub name: &'static str,
+    pub description: &'static str,
+    #[serde(flatten)]
+    pub type_: ParamType,
+    pub default: ParamValue,
+}
+
+impl ParamSpec {
+    /// Parses a value given as text, e.g. on the command line.
+    pub fn parse(&self, value: &str) -> Result<ParamValue, Error> {
+        let value = value.trim();
+        let parsed = match self.type_ {
+            ParamType::Float { .. } => value.parse().map(ParamValue::Float).map_err(|e| e.to_string()),
+            ParamType::Int { .. } => value.parse().map(ParamValue::Int).map_err(|e| e.to_string()),
+            ParamType::Bool => value.parse().map(ParamValue::Bool).map_err(|e| e.to_string()),
+        };
+        self.check(parsed.map_err(|e| Error::Method(format!("parameter {}: {}", self.name, e)))?)
+    }
+
+    /// Checks that `value` has the right type and is within range.
+    /// Integers are accepted for float parameters.
+    pub fn check(&self, value: ParamValue) -> Result<ParamValue, Error> {
+        let checked = match (self.type_, value) {
+            (ParamType::Float { min, max }, v) => v.as_f64().filter(|&v| v >= min && v <= max).map(ParamValue::Float),
+            (ParamType::Int { min, max }, ParamValue::Int(v)) => Some(v).filter(|&v| v >= min && v <= max).map(ParamValue::Int),
+            (ParamType::Bool, ParamValue::Bool(v)) => Some(ParamValue::Bool(v)),
+            _ => None
+        };
+        checked.ok_or(Error::Method(format!("parameter {} must be {}, got {}", self.name, self.type_, value)))
+    }
+}
+
+/// Parameter values by name.
+pub type Params = HashMap<String, ParamValue>;
+
 pub trait ElectoralMethod: dyn_clone::DynClone + std::any::Any {
-    fn district_size(&self) -> u32;
-    fn run(&self, stage: &ElectionStage, r: &ElectionResults, g: &Grouping) -> Result<SeatResult, String>;
+    /// Identifier of the method in `methods::METHODS`.
+    fn id(&self) -> &'static str;
+
+    /// Groupings needed by `run`. `run` receives one `Grouping`
+    /// for each entry, in the same order.
+    fn grouping_sizes(&self) -> Vec<GroupingSize>;
+    fn run(&self, stage: &ElectionStage, r: &ElectionResults, g: &[&Grouping]) -> Result<SeatResult, Error>;
+
+    /// Parameters accepted by the method.
+    fn param_specs(&self) -> Vec<ParamSpec> {
+        Vec::new()
+    }
+
+    /// Current value of every parameter in `param_specs`.
+    fn params(&self) -> Params {
+        HashMap::new()
+    }
+
+    /// Sets one parameter. `value` has already been checked
+    /// against the parameter's `ParamSpec`.
+    fn set_param(&mut self, name: &str, _value: ParamValue) -> Result<(), Error> {
+        Err(Error::Method(format!("unknown parameter {}", name)))
+    }
+
+    /// Sets every parameter from `params`, using defaults for missing ones.
+    fn set_params(&mut self, params: &Params) -> Result<(), Error> {
+        let specs = self.param_specs();
+        if let Some(name) = params.keys().find(|name| !specs.iter().any(|spec| spec.name == name.as_str())) {
+            return Err(Error::Method(format!("unknown parameter {}", name)));
+        }
+
+        for spec in specs.iter() {
+            let value = spec.check(params.get(spec.name).copied().unwrap_or(spec.default))?;
+            self.set_param(spec.name, value)?;
+        }
+        Ok(())
+    }
 
     fn as_any(&self) -> &dyn Any;
 }
 
 dyn_clone::clone_trait_object!(ElectoralMethod);
 
-pub fn encode(w: &mut (impl std::io::Write + ?Sized), data: (&ElectionStage, &ElectionResults, &HashMap<u32, Grouping>)) -> Result<(), rmps::encode::Error> {
-    data.serialize(&mut rmps::Serializer::new(w))
+/// Writes an `.elc` dataset without metadata. See `elc::write`.
+pub fn encode(w: &mut (impl std::io::Write + ?Sized), data: (&ElectionStage, &ElectionResults, &HashMap<u32, Grouping>)) -> Result<(), elc::Error> {
+    elc::write(w, &elc::Metadata::default(), data)
 }
 
-pub fn decode(r: &mut (impl std::io::Read + ?Sized)) -> Result<(ElectionStage, ElectionResults, HashMap<u32, Grouping>), rmps::decode::Error> {
-    Deserialize::deserialize(&mut rmps::Deserializer::new(r))
+/// Reads an `.elc` dataset of any supported version. See `elc::read`.
+pub fn decode(r: &mut (impl std::io::Read + ?Sized)) -> Result<(ElectionStage, ElectionResults, HashMap<u32, Grouping>), elc::Error> {
+    elc::read(r).map(|(_, data)| data)
 }
 
-pub mod utils {
-    use std::cmp::Ordering;
+/// Serializes maps and sets in key order, so that the same data always gives the same bytes.
+pub mod sorted {
+    use serde::{Serialize, Serializer};
+    use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
+    /// Wraps a map to serialize it in key order.
+    pub struct Map<'a, K, V>(pub &'a HashMap<K, V>);
+
+    impl<'a, K: Ord + Serialize, V: Serialize> Serialize for Map<'a, K, V> {
+        fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            s.collect_map(self.0.iter().collect::<BTreeMap<_, _>>())
+        }
+    }
+
+    /// Wraps a set to serialize it in order.
+    pub struct Set<'a, T>(pub &'a HashSet<T>);
+
+    impl<'a, T: Ord + Serialize> Serialize for Set<'a, T> {
+        fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            s.collect_seq(self.0.iter().collect::<BTreeSet<_>>())
+        }
+    }
+
+    pub fn map<K: Ord + Serialize, V: Serialize, S: Serializer>(map: &HashMap<K, V>, s: S) -> Result<S::Ok, S::Error> {
+        Map(map).serialize(s)
+    }
+
+    pub fn set<T: Ord + Serialize, S: Serializer>(set: &HashSet<T>, s: S) -> Result<S::Ok, S::Error> {
+        Set(set).serialize(s)
+    }
+
+    pub fn nested_map<K: Ord + Serialize, K2: Ord + Serialize, V: Serialize, S: Serializer>(map: &HashMap<K, HashMap<K2, V>>, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_map(map.iter().map(|(k, v)| (k, Map(v))).collect::<BTreeMap<_, _>>())
+    }
+
+    pub fn sets<T: Ord + Serialize, S: Serializer>(sets: &[HashSet<T>], s: S) -> Result<S::Ok, S::Error> {
+        s.collect_seq(sets.iter().map(Set))
+    }
+
+    /// The keys of `map` in order, for going through it deterministically.
+    pub fn keys<K: Copy + Ord, V>(map: &HashMap<K, V>) -> Vec<K> {
+        let mut keys: Vec<K> = map.keys().copied().collect();
+        keys.sort();
+        keys
+    }
+
+    /// The items of `set` in order.
+    pub fn items<T: Copy + Ord>(set: &HashSet<T>) -> Vec<T> {
+        let mut items: Vec<T> = set.iter().copied().collect();
+        items.sort();
+        items
+    }
+}
+
+pub mod utils {
     use crate::core::*;
-    pub fn party_candidates<'a>(stage: &'a ElectionStage, party: PartyID, candidates: impl Iterator<Item=CandidateID>  + 'a) -> impl Iterator<Item=CandidateID> + 'a {
-        candidates.filter(move |&p| stage.candidates[&p].party == Some(party))
+    /// Those of `candidates` that belong to `party`.
+    pub fn party_candidates(stage: &ElectionStage, party: PartyID, candidates: impl Iterator<Item=CandidateID>) -> Result<Vec<CandidateID>, Error> {
+        let mut list = Vec::new();
+        for candidate in candidates {
+            if stage.candidate(candidate)?.party == Some(party) {
+                list.push(candidate);
+            }
+        }
+        Ok(list)
     }
 
-    pub fn party_list<'a>(stage: &'a ElectionStage, party: PartyID, source: PartyListSource, district: DistrictID) -> impl Iterator<Item=CandidateID> + 'a {
-        utils::party_candidates(stage, party, match source {
-            PartyListSource::Area => stage.areas[&stage.districts[&district].area].candidates.iter(),
-            PartyListSource::District => stage.districts[&district].candidates.iter()
-        }.map(|&x| x))
+    /// The candidates of `party` on the list `source` gives for `district`,
+    /// in the election on `date`.
+    pub fn party_list(stage: &ElectionStage, party: PartyID, source: PartyListSource, district: DistrictID, date: Date) -> Result<Vec<CandidateID>, Error> {
+        let standing = |candidates: &HashSet<CandidateID>| -> Result<Vec<CandidateID>, Error> {
+            let mut list = Vec::new();
+            for &candidate in candidates.iter() {
+                if stage.candidate(candidate)?.stands_in(date) {
+                    list.push(candidate);
+                }
+            }
+            Ok(list)
+        };
+        let candidates = match source {
+            // Use the innermost area that has a list for the party.
+            PartyListSource::Area => {
+                let area = stage.district(district)?.area;
+                let mut list = standing(&stage.area(area)?.candidates)?;
+                for ancestor in stage.ancestors(area) {
+                    let candidates = standing(&stage.area(ancestor)?.candidates)?;
+                    if !party_candidates(stage, party, candidates.iter().copied())?.is_empty() {
+                        list = candidates;
+                        break
+                    }
+                }
+                list
+            },
+            PartyListSource::District => standing(&stage.district(district)?.candidates)?
+        };
+        party_candidates(stage, party, candidates.into_iter())
     }
 
-    pub fn seats_by_party(stage: &ElectionStage, seats: &SeatResult) -> HashMap<Option<PartyID>, usize> {
-        let mut parties = HashMap::new();
-        for seat_idx in &seats.seats {
-            let item = match stage.candidates[seat_idx].party {
-                Some(party) => Some(party),
-                None => None
+    /// Votes for each party in each area at `level`. Uses party votes where a
+    /// district has them, and otherwise candidate votes.
+    pub fn party_votes_by_area(stage: &ElectionStage, results: &ElectionResults, level: usize) -> HashMap<AreaID, HashMap<PartyID, u32>> {
+        let mut votes: HashMap<AreaID, HashMap<PartyID, u32>> = HashMap::new();
+        for (id, dres) in results.districts.iter() {
+            let area = match stage.districts.get(id) {
+                Some(district) => stage.area_at_level(district.area, level),
+                None => continue
             };
+            let area_votes = votes.entry(area).or_default();
+
+            if !dres.party_votes.is_empty() {
+                for (&party, &v) in dres.party_votes.iter() {
+                    *area_votes.entry(party).or_insert(0) += v;
+                }
+            } else {
+                for (candidate, &v) in dres.candidate_votes.iter() {
+                    if let Some(party) = stage.candidates.get(candidate).and_then(|c| c.party) {
+                        *area_votes.entry(party).or_insert(0) += v;
+                    }
+                }
+            }
+        }
+        votes
+    }
+
+    /// Parties with at least `threshold` of the vote in each area at `level`.
+    pub fn parties_over_threshold(stage: &ElectionStage, results: &ElectionResults, threshold: f64, level: usize) -> HashMap<AreaID, HashSet<PartyID>> {
+        party_votes_by_area(stage, results, level).into_iter().map(|(area, votes)| {
+            let total: u64 = votes.values().map(|&v| v as u64).sum();
+            let parties = votes.iter().filter(|(_, &v)| total > 0 && v as f64 / total as f64 >= threshold).map(|(&p, _)| p).collect();
+            (area, parties)
+        }).collect()
+    }
+
+    /// Seats for each party in each area at `level`.
+    pub fn seats_by_area(stage: &ElectionStage, seats: &SeatResult, level: usize) -> Result<HashMap<AreaID, HashMap<Option<PartyID>, usize>>, Error> {
+        let mut candidate_areas: HashMap<CandidateID, AreaID> = HashMap::new();
+        for (&id, area) in stage.areas.iter() {
+            candidate_areas.extend(area.candidates.iter().map(|&c| (c, id)));
+        }
+        for district in stage.districts.values() {
+            candidate_areas.extend(district.candidates.iter().map(|&c| (c, district.area)));
+        }
+
+        let mut areas: HashMap<AreaID, Vec<CandidateID>> = HashMap::new();
+        for candidate in seats.seats.iter() {
+            let &area = candidate_areas.get(candidate)
+                .ok_or(Error::Inconsistent(format!("candidate {} won a seat but isn't in any district or area", candidate)))?;
+            areas.entry(stage.area_at_level(area, level)).or_default().push(*candidate);
+        }
+
+        areas.into_iter().map(|(area, candidates)| {
+            Ok((area, seats_by_party(stage, &SeatResult { seats: candidates.into_iter().collect() })?))
+        }).collect()
+    }
+
+    pub fn seats_by_party(stage: &ElectionStage, seats: &SeatResult) -> Result<HashMap<Option<PartyID>, usize>, Error> {
+        let mut parties = HashMap::new();
+        for &seat_idx in &seats.seats {
+            let item = stage.candidate(seat_idx)?.party;
 
             let new_count = parties.get(&item).unwrap_or(&0) + 1;
             parties.insert(item, new_count);
         }
 
-        parties
+        Ok(parties)
     }
+}
 
-    pub fn allocate_sainte_lague(pops: &[u32], seats: SeatCount) -> Box<[SeatCount]> {
-        let total_pop: u32 = pops.iter().sum();
-        let mut seat_arr = vec![0 as SeatCount; pops.len()].into_boxed_slice();
-
-        while seat_arr.iter().map(|&x| x).sum::<SeatCount>() < seats {
-            let mut quotients = vec![0f64;pops.len()].into_boxed_slice();
-            for (i, &pop) in pops.iter().enumerate() {
-                quotients[i] = pops[i] as f64 / (2*seat_arr[i] + 1) as f64;
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-            seat_arr[quotients.iter().enumerate().max_by(|(_, &val), (_, &val2)| PartialOrd::partial_cmp(&val, &val2).unwrap()).unwrap().0] += 1;
+    /// One district in one area, where party `name` has list candidate `id`
+    /// and district candidate `id + 1`, who won `votes`.
+    fn election(parties: &[(&str, CandidateID, u32)], date: Date) -> (ElectionStage, ElectionResults) {
+        let mut builder = ElectionStage::builder();
+        builder.area(1, "Area", None).district(1, "District", 1, 1);
+        let mut results = DistrictResults {
+            party_votes: HashMap::new(),
+            party_list_source: PartyListSource::Area,
+            candidate_votes: HashMap::new(),
+            electorate: None,
+            ballots: None,
+            invalid: None,
+            invalid_party: None,
+        };
+        for (i, &(name, id, votes)) in parties.iter().enumerate() {
+            let party = i as PartyID;
+            builder.party(party, Party { name: name.to_owned(), color: 0, type_: PartyType::Other, abbreviation: None, names: HashMap::new() })
+                .list_candidate(id, None, party, 1)
+                .candidate(id + 1, None, Some(party), 1);
+            results.party_votes.insert(party, votes);
+            results.candidate_votes.insert(id + 1, votes);
         }
+        let districts = std::iter::once((1, results)).collect();
+        (builder.build().unwrap(), ElectionResults { districts, date, units: HashMap::new() })
+    }
+
+    #[test]
+    fn added_elections_keep_their_own_lists() {
+        let (mut stage, results) = election(&[("A", 0, 10), ("B", 2, 20)], Date::new(2017, 9, 24));
+        let (previous_stage, previous) = election(&[("B", 0, 30), ("A", 2, 40)], Date::new(2013, 9, 22));
+        let previous = stage.add_election(results.date, &previous_stage, &previous).unwrap();
+        assert_eq!(stage.candidates.len(), 8);
+
+        // Party A is 0 on this stage, and was 1 on the previous one.
+        let list = |date| utils::party_list(&stage, 0, PartyListSource::Area, 1, date).unwrap();
+        assert_eq!(list(results.date), vec![0]);
+        assert_eq!(list(previous.date), vec![6]);
 
-        seat_arr
+        assert!(validate::validate(&stage, &results).is_ok());
+        assert!(validate::validate(&stage, &previous).is_ok());
     }
 }
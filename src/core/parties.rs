@@ -0,0 +1,172 @@
+//! A registry of parties by country: names in several languages, abbreviations,
+//! colours and ideology. The importers copy what it knows into each dataset's
+//! `Party`, which is where the UI reads it. The built-in registry is
+//! `parties.toml` at the root of the repository:
+//!
+//! ```toml
+//! [germany.spd]
+//! name = "Social Democrats"
+//! names = { de = "Sozialdemokratische Partei Deutschlands" }
+//! abbreviation = "SPD"
+//! color = "e2001a"
+//! ideology = "social-democratic"
+//! ```
+//!
+//! Datasets can override it with a file of the same form, see `Registry::merge`.
+use super::*;
+use std::ffi::OsStr;
+use std::path::Path;
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PartyInfo {
+    /// The English name, which datasets use.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Names by language code, e.g. `fr`.
+    #[serde(default)]
+    pub names: HashMap<String, String>,
+    #[serde(default)]
+    pub abbreviation: Option<String>,
+    /// Hex, like `14294d`.
+    #[serde(default, deserialize_with = "hex_color")]
+    pub color: Option<u32>,
+    #[serde(default)]
+    pub ideology: Option<PartyType>,
+    /// Other names the party goes by in source data.
+    #[serde(default)]
+    pub aliases: Vec<String>,
+}
+
+fn hex_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+    let color = String::deserialize(deserializer)?;
+    u32::from_str_radix(color.trim_start_matches('#'), 16)
+        .map(Some)
+        .map_err(|e| <D::Error as de::Error>::custom(format!("color {}: {}", color, e)))
+}
+
+impl PartyInfo {
+    /// The name in `language`, or the English name.
+    pub fn name_in(&self, language: &str) -> Option<&str> {
+        self.names.get(language).or(self.name.as_ref()).map(String::as_str)
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        self.name.as_deref() == Some(name)
+            || self.abbreviation.as_deref() == Some(name)
+            || self.names.values().any(|n| n == name)
+            || self.aliases.iter().any(|n| n == name)
+    }
+
+    /// Sets the fields `other` has, adding to its names and aliases.
+    fn merge(&mut self, other: PartyInfo) {
+        if other.name.is_some() {
+            self.name = other.name;
+        }
+        self.names.extend(other.names);
+        if other.abbreviation.is_some() {
+            self.abbreviation = other.abbreviation;
+        }
+        if other.color.is_some() {
+            self.color = other.color;
+        }
+        if other.ideology.is_some() {
+            self.ideology = other.ideology;
+        }
+        self.aliases.extend(other.aliases);
+    }
+}
+
+fn find_key<'a>(parties: &'a HashMap<String, PartyInfo>, name: &str) -> Option<&'a String> {
+    if let Some((key, _)) = parties.get_key_value(name) {
+        return Some(key);
+    }
+    let mut keys: Vec<&String> = parties.keys().filter(|key| parties[*key].matches(name)).collect();
+    keys.sort();
+    keys.first().copied()
+}
+
+/// Parties by country and key, e.g. `germany` and `spd`. Countries are matched
+/// ignoring case, so `Germany` from `elc::Metadata` finds them.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Registry {
+    countries: HashMap<String, HashMap<String, PartyInfo>>,
+}
+
+impl Registry {
+    /// The registry in `parties.toml`.
+    pub fn builtin() -> Registry {
+        Registry::from_toml(include_str!("../../parties.toml")).expect("parties.toml is a valid registry")
+    }
+
+    pub fn from_toml(text: &str) -> Result<Registry, Error> {
+        toml::from_str::<Registry>(text).map(Registry::normalized).map_err(|e| Error::Source(Box::new(e)))
+    }
+
+    pub fn from_json(text: &str) -> Result<Registry, Error> {
+        serde_json::from_str::<Registry>(text).map(Registry::normalized).map_err(|e| Error::Source(Box::new(e)))
+    }
+
+    /// Reads a `.toml` or JSON file.
+    pub fn load(path: &Path) -> Result<Registry, Error> {
+        let text = std::fs::read_to_string(path).map_err(|e| Error::Source(Box::new(e)))?;
+        if path.extension() == Some(OsStr::new("toml")) {
+            Registry::from_toml(&text)
+        } else {
+            Registry::from_json(&text)
+        }
+    }
+
+    fn normalized(self) -> Registry {
+        let mut registry = Registry::default();
+        registry.merge(self);
+        registry
+    }
+
+    /// Adds the parties of `overrides`. A party whose key is already known,
+    /// or is the name of a known party as in `find`, only changes the fields it gives.
+    /// Countries and parties are merged in order of key, so that the result doesn't
+    /// depend on the order of the maps.
+    pub fn merge(&mut self, overrides: Registry) {
+        let mut countries: Vec<(String, HashMap<String, PartyInfo>)> = overrides.countries.into_iter().collect();
+        countries.sort_by(|a, b| a.0.cmp(&b.0));
+        for (country, parties) in countries {
+            let known = self.countries.entry(country.to_lowercase()).or_default();
+            let mut parties: Vec<(String, PartyInfo)> = parties.into_iter().collect();
+            parties.sort_by(|a, b| a.0.cmp(&b.0));
+            for (key, info) in parties {
+                let key = find_key(known, &key).cloned().unwrap_or(key);
+                known.entry(key).or_default().merge(info);
+            }
+        }
+    }
+
+    /// Adds or changes the parties of one country, as given by a dataset.
+    pub fn merge_country(&mut self, country: &str, parties: HashMap<String, PartyInfo>) {
+        let mut countries = HashMap::with_capacity(1);
+        countries.insert(country.to_owned(), parties);
+        self.merge(Registry { countries });
+    }
+
+    /// The party that goes by `name` in `country`: the one with that key, or
+    /// else the first by key with that name, abbreviation or alias.
+    pub fn find(&self, country: &str, name: &str) -> Option<&PartyInfo> {
+        let parties = self.countries.get(&country.to_lowercase())?;
+        find_key(parties, name).map(|key| &parties[key])
+    }
+
+    /// The party that goes by `name` in `country`, with what the registry
+    /// knows about it, so that datasets carry it. Parties that aren't
+    /// registered keep that name and are grey.
+    pub fn party(&self, country: &str, name: &str) -> Party {
+        let info = self.find(country, name);
+        Party {
+            name: info.and_then(|info| info.name.clone()).unwrap_or_else(|| name.to_owned()),
+            color: info.and_then(|info| info.color).unwrap_or(0xaaaaaa),
+            type_: info.and_then(|info| info.ideology).unwrap_or_default(),
+            abbreviation: info.and_then(|info| info.abbreviation.clone()),
+            names: info.map(|info| info.names.clone()).unwrap_or_default(),
+        }
+    }
+}
@@ -0,0 +1,578 @@
+//! A flat CSV form of a dataset, one row per fact, for spreadsheets and data tools.
+//!
+//! The `row` column says what each row describes, and which other columns it uses:
+//!
+//! - `metadata`: field `name` of `elc::Metadata` is `value`, e.g. `country` and
+//!   `Canada`. `created` is a date like `2019-10-21`.
+//! - `party`: `party`, `name`, `party_type`, `color` (hex, like `14294d`)
+//! - `abbreviation`: `party` is abbreviated `name`
+//! - `party-name`: `party` is called `name` in `language`
+//! - `area`: `area`, `name`, `seats`, `population`, `parent`
+//! - `district`: `district`, `area`, `name`, `seats`
+//! - `unit`: `unit`, `district`, `name`
+//! - `candidate`: `candidate`, `name`, `party`, and the `district` or list `area`
+//!   the candidate stands in. Repeated for each district and list. An empty
+//!   `name` is no name, unless `empty_name` is `true`. `date` is the election
+//!   they stand in, if the dataset has more than one.
+//! - `group`: `district` is in group `group` of the grouping for size `grouping`
+//! - `adjacent`, `unit-adjacent`: `district` or `unit` borders `neighbour`,
+//!   with a shared boundary of `length`. Each edge is written once.
+//! - `boundary`: point `x`, `y` of ring `ring` of `district`'s boundary, in order.
+//!   A ring or district without points has a row without them.
+//! - `election`: results for `date` follow, the main election first
+//! - `totals`: `date`, the `district` or `unit`, `list_source`, `electorate`,
+//!   `ballots`, `invalid`, `invalid_party`
+//! - `votes`: `date`, the `district` or `unit`, and the `candidate` or `party`
+//!   that won `votes`. `name` repeats the candidate's or party's name.
+use super::*;
+use std::io;
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct Row {
+    row: String,
+    date: Option<String>,
+    area: Option<AreaID>,
+    district: Option<DistrictID>,
+    unit: Option<UnitID>,
+    candidate: Option<CandidateID>,
+    party: Option<PartyID>,
+    name: Option<String>,
+    language: Option<String>,
+    value: Option<String>,
+    votes: Option<u32>,
+    seats: Option<SeatCount>,
+    population: Option<u32>,
+    parent: Option<AreaID>,
+    party_type: Option<PartyType>,
+    color: Option<String>,
+    list_source: Option<PartyListSource>,
+    electorate: Option<u32>,
+    ballots: Option<u32>,
+    invalid: Option<u32>,
+    invalid_party: Option<u32>,
+    grouping: Option<u32>,
+    group: Option<usize>,
+    neighbour: Option<u32>,
+    length: Option<f64>,
+    empty_name: Option<bool>,
+    ring: Option<usize>,
+    x: Option<f64>,
+    y: Option<f64>,
+}
+
+fn metadata_rows(metadata: &elc::Metadata) -> Vec<Row> {
+    let fields = [
+        ("country", metadata.country.clone()),
+        ("election", metadata.election.clone()),
+        ("source_url", metadata.source_url.clone()),
+        ("importer", metadata.importer.clone()),
+        ("created", metadata.created.map(|date| date.to_string())),
+    ];
+    fields.iter()
+        .filter_map(|(name, value)| value.as_ref().map(|value| Row { row: "metadata".to_owned(), name: Some(name.to_string()), value: Some(value.clone()), ..Row::default() }))
+        .collect()
+}
+
+fn boundary_rows(geometry: &Boundaries) -> Vec<Row> {
+    let mut rows = Vec::new();
+    for id in sorted::keys(geometry) {
+        let district = Some(id);
+        if geometry[&id].is_empty() {
+            rows.push(Row { row: "boundary".to_owned(), district, ..Row::default() });
+        }
+        for (ring, points) in geometry[&id].iter().enumerate() {
+            if points.is_empty() {
+                rows.push(Row { row: "boundary".to_owned(), district, ring: Some(ring), ..Row::default() });
+            }
+            for &(x, y) in points.iter() {
+                rows.push(Row { row: "boundary".to_owned(), district, ring: Some(ring), x: Some(x), y: Some(y), ..Row::default() });
+            }
+        }
+    }
+    // Keeps geometry without districts apart from none at all.
+    if rows.is_empty() {
+        rows.push(Row { row: "boundary".to_owned(), ..Row::default() });
+    }
+    rows
+}
+
+fn adjacency_rows(kind: &str, adjacency: &Adjacency, row: impl Fn(u32) -> Row) -> Vec<Row> {
+    let mut rows = Vec::new();
+    for from in sorted::keys(&adjacency.0) {
+        for to in sorted::keys(&adjacency.0[&from]).into_iter().filter(|&to| to > from) {
+            rows.push(Row { row: kind.to_owned(), neighbour: Some(to), length: adjacency.0[&from][&to], ..row(from) });
+        }
+    }
+    // Keeps an adjacency without edges apart from none at all.
+    if rows.is_empty() {
+        rows.push(Row { row: kind.to_owned(), ..Row::default() });
+    }
+    rows
+}
+
+fn results_rows(stage: &ElectionStage, results: &ElectionResults) -> Vec<Row> {
+    let date = Some(results.date.to_string());
+    let mut rows = vec![Row { row: "election".to_owned(), date: date.clone(), ..Row::default() }];
+    let mut add = |place: Row, dres: &DistrictResults| {
+        rows.push(Row {
+            row: "totals".to_owned(),
+            date: date.clone(),
+            list_source: Some(dres.party_list_source),
+            electorate: dres.electorate,
+            ballots: dres.ballots,
+            invalid: dres.invalid,
+            invalid_party: dres.invalid_party,
+            ..place.clone()
+        });
+        for candidate in sorted::keys(&dres.candidate_votes) {
+            let info = stage.candidates.get(&candidate);
+            rows.push(Row {
+                row: "votes".to_owned(),
+                date: date.clone(),
+                candidate: Some(candidate),
+                party: info.and_then(|c| c.party),
+                name: info.and_then(|c| c.name.clone()),
+                votes: Some(dres.candidate_votes[&candidate]),
+                ..place.clone()
+            });
+        }
+        for party in sorted::keys(&dres.party_votes) {
+            rows.push(Row {
+                row: "votes".to_owned(),
+                date: date.clone(),
+                party: Some(party),
+                name: stage.parties.get(&party).map(|p| p.name.clone()),
+                votes: Some(dres.party_votes[&party]),
+                ..place.clone()
+            });
+        }
+    };
+    for id in sorted::keys(&results.districts) {
+        add(Row { district: Some(id), ..Row::default() }, &results.districts[&id]);
+    }
+    for id in sorted::keys(&results.units) {
+        add(Row { unit: Some(id), district: stage.units.get(&id).map(|u| u.district), ..Row::default() }, &results.units[&id]);
+    }
+    rows
+}
+
+/// Writes `metadata` and everything in `sections`.
+pub fn write(w: impl io::Write, metadata: &elc::Metadata, sections: &elc::Sections) -> Result<(), Error> {
+    let stage = sections.stage;
+    let mut rows = metadata_rows(metadata);
+
+    for id in sorted::keys(&stage.parties) {
+        let party = &stage.parties[&id];
+        rows.push(Row {
+            row: "party".to_owned(),
+            party: Some(id),
+            name: Some(party.name.clone()),
+            party_type: Some(party.type_),
+            color: Some(format!("{:06x}", party.color)),
+            ..Row::default()
+        });
+        if let Some(abbreviation) = &party.abbreviation {
+            rows.push(Row { row: "abbreviation".to_owned(), party: Some(id), name: Some(abbreviation.clone()), ..Row::default() });
+        }
+        let mut languages: Vec<&String> = party.names.keys().collect();
+        languages.sort();
+        for language in languages {
+            rows.push(Row { row: "party-name".to_owned(), party: Some(id), language: Some(language.clone()), name: Some(party.names[language].clone()), ..Row::default() });
+        }
+    }
+    for id in sorted::keys(&stage.areas) {
+        let area = &stage.areas[&id];
+        rows.push(Row {
+            row: "area".to_owned(),
+            area: Some(id),
+            name: Some(area.name.clone()),
+            seats: Some(area.seats),
+            population: area.population,
+            parent: area.parent,
+            ..Row::default()
+        });
+    }
+    for id in sorted::keys(&stage.districts) {
+        let district = &stage.districts[&id];
+        rows.push(Row { row: "district".to_owned(), district: Some(id), area: Some(district.area), name: Some(district.name.clone()), seats: Some(district.seats), ..Row::default() });
+    }
+    for id in sorted::keys(&stage.units) {
+        let unit = &stage.units[&id];
+        rows.push(Row { row: "unit".to_owned(), unit: Some(id), district: Some(unit.district), name: Some(unit.name.clone()), ..Row::default() });
+    }
+
+    let mut places: HashMap<CandidateID, Vec<Row>> = HashMap::new();
+    for id in sorted::keys(&stage.districts) {
+        for candidate in sorted::items(&stage.districts[&id].candidates) {
+            places.entry(candidate).or_default().push(Row { district: Some(id), ..Row::default() });
+        }
+    }
+    for id in sorted::keys(&stage.areas) {
+        for candidate in sorted::items(&stage.areas[&id].candidates) {
+            places.entry(candidate).or_default().push(Row { area: Some(id), ..Row::default() });
+        }
+    }
+    for id in sorted::keys(&stage.candidates) {
+        let candidate = &stage.candidates[&id];
+        for place in places.remove(&id).unwrap_or_else(|| vec![Row::default()]) {
+            rows.push(Row {
+                row: "candidate".to_owned(),
+                candidate: Some(id),
+                name: candidate.name.clone(),
+                empty_name: candidate.name.as_ref().filter(|name| name.is_empty()).map(|_| true),
+                party: candidate.party,
+                date: candidate.election.map(|date| date.to_string()),
+                ..place
+            });
+        }
+    }
+
+    for size in sorted::keys(sections.groupings) {
+        let grouping = &sections.groupings[&size];
+        for (index, group) in grouping.iter() {
+            let districts = sorted::items(group);
+            // An empty group still gets a row, so that later groups keep their index.
+            let districts: Vec<Option<DistrictID>> = if districts.is_empty() { vec![None] } else { districts.into_iter().map(Some).collect() };
+            for district in districts {
+                rows.push(Row { row: "group".to_owned(), grouping: Some(size), group: Some(index), district, ..Row::default() });
+            }
+        }
+        if grouping.0.is_empty() {
+            rows.push(Row { row: "group".to_owned(), grouping: Some(size), ..Row::default() });
+        }
+    }
+
+    if let Some(adjacency) = &stage.adjacency {
+        rows.extend(adjacency_rows("adjacent", adjacency, |id| Row { district: Some(id), ..Row::default() }));
+    }
+    if let Some(adjacency) = &stage.unit_adjacency {
+        rows.extend(adjacency_rows("unit-adjacent", adjacency, |id| Row { unit: Some(id), ..Row::default() }));
+    }
+    if let Some(geometry) = sections.geometry {
+        rows.extend(boundary_rows(geometry));
+    }
+
+    rows.extend(results_rows(stage, sections.results));
+    for results in sections.other_results.iter() {
+        rows.extend(results_rows(stage, results));
+    }
+
+    let mut writer = csv::Writer::from_writer(w);
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    writer.flush().map_err(|e| Error::Source(Box::new(e)))?;
+    Ok(())
+}
+
+/// Reads a dataset written by `write`.
+pub fn read(r: impl io::Read) -> Result<elc::Contents, Error> {
+    let mut metadata = elc::Metadata::default();
+    let mut geometry: Option<Boundaries> = None;
+    let mut stage = ElectionStage {
+        districts: HashMap::new(),
+        candidates: HashMap::new(),
+        parties: HashMap::new(),
+        areas: HashMap::new(),
+        adjacency: None,
+        units: HashMap::new(),
+        unit_adjacency: None,
+    };
+    let mut groupings = Groupings::new();
+    let mut elections: Vec<ElectionResults> = Vec::new();
+    let mut dates: HashMap<Date, usize> = HashMap::new();
+
+    let mut reader = csv::Reader::from_reader(r);
+    for (i, row) in reader.deserialize::<Row>().enumerate() {
+        let row = row?;
+        // Line 1 is the header.
+        let line = i + 2;
+        let error = |msg: String| Error::Inconsistent(format!("line {}: {}", line, msg));
+        let missing = |column: &str| error(format!("{} row has no {}", row.row, column));
+
+        match row.row.as_str() {
+            "metadata" => {
+                // The row is only written for fields that are set, so an empty value is an empty field.
+                let value = row.value.clone().unwrap_or_default();
+                match row.name.as_deref().ok_or_else(|| missing("name"))? {
+                    "country" => metadata.country = Some(value),
+                    "election" => metadata.election = Some(value),
+                    "source_url" => metadata.source_url = Some(value),
+                    "importer" => metadata.importer = Some(value),
+                    "created" => metadata.created = Some(value.parse().map_err(|e: Error| error(e.to_string()))?),
+                    // Fields of later versions are skipped, as in `elc::Metadata`.
+                    _ => {}
+                }
+            },
+            "party" => {
+                let color = row.color.as_ref().ok_or_else(|| missing("color"))?;
+                let color = u32::from_str_radix(color.trim_start_matches('#'), 16).map_err(|e| error(format!("color {}: {}", color, e)))?;
+                stage.parties.insert(row.party.ok_or_else(|| missing("party"))?, Party {
+                    name: row.name.clone().unwrap_or_default(),
+                    type_: row.party_type.unwrap_or_default(),
+                    color,
+                    abbreviation: None,
+                    names: HashMap::new(),
+                });
+            },
+            "abbreviation" | "party-name" => {
+                let id = row.party.ok_or_else(|| missing("party"))?;
+                let party = stage.parties.get_mut(&id).ok_or_else(|| error(format!("party {} comes later or not at all", id)))?;
+                let name = row.name.clone().unwrap_or_default();
+                if row.row == "abbreviation" {
+                    party.abbreviation = Some(name);
+                } else {
+                    party.names.insert(row.language.clone().ok_or_else(|| missing("language"))?, name);
+                }
+            },
+            "area" => {
+                stage.areas.insert(row.area.ok_or_else(|| missing("area"))?, Area {
+                    name: row.name.clone().unwrap_or_default(),
+                    seats: row.seats.unwrap_or(0),
+                    population: row.population,
+                    parent: row.parent,
+                    districts: HashSet::new(),
+                    candidates: HashSet::new(),
+                });
+            },
+            "district" => {
+                let id = row.district.ok_or_else(|| missing("district"))?;
+                let area = row.area.ok_or_else(|| missing("area"))?;
+                stage.districts.insert(id, District { name: row.name.clone().unwrap_or_default(), seats: row.seats.unwrap_or(1), area, candidates: HashSet::new() });
+                stage.areas.get_mut(&area).ok_or_else(|| error(format!("district {} is in area {}, which comes later or not at all", id, area)))?
+                    .districts.insert(id);
+            },
+            "unit" => {
+                let id = row.unit.ok_or_else(|| missing("unit"))?;
+                stage.units.insert(id, Unit { name: row.name.clone().unwrap_or_default(), district: row.district.ok_or_else(|| missing("district"))? });
+            },
+            "candidate" => {
+                let id = row.candidate.ok_or_else(|| missing("candidate"))?;
+                let name = if row.empty_name == Some(true) { Some(String::new()) } else { row.name.clone() };
+                let election = match &row.date {
+                    Some(date) => Some(date.parse().map_err(|e: Error| error(e.to_string()))?),
+                    None => None,
+                };
+                stage.candidates.insert(id, Candidate { name, party: row.party, election });
+                if let Some(district) = row.district {
+                    stage.districts.get_mut(&district).ok_or_else(|| error(format!("candidate {} stands in district {}, which comes later or not at all", id, district)))?
+                        .candidates.insert(id);
+                } else if let Some(area) = row.area {
+                    stage.areas.get_mut(&area).ok_or_else(|| error(format!("candidate {} is on a list in area {}, which comes later or not at all", id, area)))?
+                        .candidates.insert(id);
+                }
+            },
+            "group" => {
+                let grouping = &mut groupings.entry(row.grouping.ok_or_else(|| missing("grouping"))?).or_insert_with(|| Grouping(Vec::new())).0;
+                if let Some(group) = row.group {
+                    if grouping.len() <= group {
+                        grouping.resize(group + 1, HashSet::new());
+                    }
+                    grouping[group].extend(row.district);
+                }
+            },
+            "adjacent" | "unit-adjacent" => {
+                let adjacency = if row.row == "adjacent" { &mut stage.adjacency } else { &mut stage.unit_adjacency };
+                let adjacency = adjacency.get_or_insert_with(Adjacency::new);
+                if let Some(neighbour) = row.neighbour {
+                    let from = if row.row == "adjacent" { row.district } else { row.unit };
+                    adjacency.add(from.ok_or_else(|| missing(if row.row == "adjacent" { "district" } else { "unit" }))?, neighbour, row.length);
+                }
+            },
+            "boundary" => {
+                let geometry = geometry.get_or_insert_with(HashMap::new);
+                if let Some(district) = row.district {
+                    let rings = geometry.entry(district).or_default();
+                    if let Some(ring) = row.ring {
+                        if rings.len() <= ring {
+                            rings.resize(ring + 1, Vec::new());
+                        }
+                        match (row.x, row.y) {
+                            (Some(x), Some(y)) => rings[ring].push((x, y)),
+                            (None, None) => {},
+                            (None, _) => return Err(missing("x")),
+                            (_, None) => return Err(missing("y")),
+                        }
+                    }
+                }
+            },
+            "election" | "totals" | "votes" => {
+                let date: Date = row.date.as_ref().ok_or_else(|| missing("date"))?.parse().map_err(|e: Error| error(e.to_string()))?;
+                if row.row == "election" {
+                    dates.insert(date, elections.len());
+                    elections.push(ElectionResults { districts: HashMap::new(), date, units: HashMap::new() });
+                    continue
+                }
+                let results = &mut elections[*dates.get(&date).ok_or_else(|| error(format!("no election row for {} before this", date)))?];
+                let (map, id) = match (row.unit, row.district) {
+                    (Some(unit), _) => (&mut results.units, unit),
+                    (None, Some(district)) => (&mut results.districts, district),
+                    (None, None) => return Err(missing("district or unit")),
+                };
+                let dres = map.entry(id).or_insert_with(|| DistrictResults {
+                    party_votes: HashMap::new(),
+                    party_list_source: PartyListSource::District,
+                    candidate_votes: HashMap::new(),
+                    electorate: None,
+                    ballots: None,
+                    invalid: None,
+                    invalid_party: None,
+                });
+                if row.row == "totals" {
+                    dres.party_list_source = row.list_source.ok_or_else(|| missing("list_source"))?;
+                    dres.electorate = row.electorate;
+                    dres.ballots = row.ballots;
+                    dres.invalid = row.invalid;
+                    dres.invalid_party = row.invalid_party;
+                } else {
+                    let votes = row.votes.ok_or_else(|| missing("votes"))?;
+                    match (row.candidate, row.party) {
+                        (Some(candidate), _) => { dres.candidate_votes.insert(candidate, votes); },
+                        (None, Some(party)) => { dres.party_votes.insert(party, votes); },
+                        (None, None) => return Err(missing("candidate or party")),
+                    }
+                }
+            },
+            other => return Err(error(format!("unknown row type {:?}", other))),
+        }
+    }
+
+    if elections.is_empty() {
+        return Err(Error::Inconsistent("no election rows".to_owned()));
+    }
+    let results = elections.remove(0);
+    elections.sort_by_key(|r| r.date);
+    Ok(elc::Contents {
+        metadata,
+        stage,
+        results,
+        groupings,
+        geometry,
+        other_results: elections,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dres(candidate_votes: &[(CandidateID, u32)], party_votes: &[(PartyID, u32)], electorate: Option<u32>) -> DistrictResults {
+        DistrictResults {
+            party_votes: party_votes.iter().copied().collect(),
+            party_list_source: PartyListSource::Area,
+            candidate_votes: candidate_votes.iter().copied().collect(),
+            electorate,
+            ballots: electorate.map(|e| e / 2),
+            invalid: None,
+            invalid_party: Some(0),
+        }
+    }
+
+    fn contents() -> elc::Contents {
+        let mut adjacency = Adjacency::new();
+        adjacency.add(1, 2, Some(0.1 + 0.2));
+        adjacency.add(2, 3, None);
+        let stage = ElectionStage::builder()
+            .party(0, Party {
+                name: "Red".into(),
+                color: 0xd71920,
+                type_: PartyType::SocialDemocratic,
+                abbreviation: Some("R".into()),
+                names: [("fr".to_owned(), "Rouge".to_owned()), ("de".to_owned(), "".to_owned())].iter().cloned().collect(),
+            })
+            .party(1, Party { name: "Blue, \"Tories\"".into(), color: 0x1a4782, type_: PartyType::Conservative, abbreviation: Some("".into()), names: HashMap::new() })
+            .area(1, "Country", None)
+            .population(1, 1000)
+            .area(2, "Province", Some(1))
+            .area_seats(2, 1)
+            .district(1, "North", 2, 1)
+            .district(2, "South", 2, 1)
+            .district(3, "", 2, 2)
+            .unit(0, "1-1", 1)
+            .unit(1, "1-2", 1)
+            .candidate(10, Some("Ann".into()), Some(0), 1)
+            .candidate(11, None, Some(1), 1)
+            .candidate(12, Some("".into()), None, 2)
+            .dual_candidate(13, Some("Bo".into()), 0, 2, 2)
+            .list_candidate(14, None, 1, 2)
+            .candidate(15, None, Some(0), 3)
+            .adjacency(adjacency)
+            .unit_adjacency(Adjacency::new())
+            .build()
+            .unwrap();
+
+        let mut results = ElectionResults { districts: HashMap::new(), date: Date::new(2019, 10, 21), units: HashMap::new() };
+        results.districts.insert(1, dres(&[(10, 300), (11, 250)], &[(0, 310), (1, 240)], Some(900)));
+        results.districts.insert(2, dres(&[(12, 5), (13, 0)], &[], None));
+        results.districts.insert(3, dres(&[], &[(0, 7)], None));
+        results.units.insert(0, dres(&[(10, 100), (11, 50)], &[], Some(300)));
+        results.units.insert(1, dres(&[(10, 200), (11, 200)], &[], None));
+        let mut earlier = ElectionResults { districts: HashMap::new(), date: Date::new(2015, 10, 19), units: HashMap::new() };
+        earlier.districts.insert(1, dres(&[(10, 1)], &[], None));
+
+        let mut groupings = Groupings::new();
+        groupings.insert(1, Grouping(vec![[1].iter().copied().collect(), [2, 3].iter().copied().collect()]));
+        groupings.insert(2, Grouping(vec![HashSet::new(), [1, 2, 3].iter().copied().collect()]));
+        groupings.insert(5, Grouping(Vec::new()));
+
+        let mut geometry = Boundaries::new();
+        geometry.insert(1, vec![vec![(0.0, 0.0), (1.0 / 3.0, -2.5e-7), (0.5, 1.0)], Vec::new(), vec![(2.0, 2.0)]]);
+        geometry.insert(2, Vec::new());
+
+        elc::Contents {
+            metadata: elc::Metadata {
+                country: Some("Canada".into()),
+                election: Some("".into()),
+                source_url: None,
+                importer: Some("vsim2-extract 0.1.0".into()),
+                created: Some(Date::new(2020, 2, 29)),
+            },
+            stage,
+            results,
+            groupings,
+            geometry: Some(geometry),
+            other_results: vec![earlier],
+        }
+    }
+
+    /// Everything in `contents`, in a form that can be compared.
+    fn json(contents: &elc::Contents) -> Vec<String> {
+        vec![
+            serde_json::to_string(&contents.metadata).unwrap(),
+            serde_json::to_string(&contents.stage).unwrap(),
+            serde_json::to_string(&contents.results).unwrap(),
+            serde_json::to_string(&sorted::Map(&contents.groupings)).unwrap(),
+            serde_json::to_string(&contents.geometry.as_ref().map(sorted::Map)).unwrap(),
+            serde_json::to_string(&contents.other_results).unwrap(),
+        ]
+    }
+
+    fn round_trip(contents: &elc::Contents) -> elc::Contents {
+        let mut csv = Vec::new();
+        write(&mut csv, &contents.metadata, &contents.sections()).unwrap();
+        read(&csv[..]).unwrap()
+    }
+
+    #[test]
+    fn round_trip_is_lossless() {
+        let contents = contents();
+        let read = round_trip(&contents);
+        assert_eq!(json(&read), json(&contents));
+        assert_eq!(read.stage.candidates[&12].name.as_deref(), Some(""));
+        assert_eq!(read.stage.candidates[&11].name, None);
+    }
+
+    #[test]
+    fn round_trip_keeps_missing_parts_missing() {
+        let mut contents = contents();
+        contents.metadata = elc::Metadata::default();
+        contents.geometry = None;
+        contents.stage.adjacency = None;
+        contents.stage.unit_adjacency = None;
+        contents.other_results.clear();
+        assert_eq!(json(&round_trip(&contents)), json(&contents));
+
+        contents.geometry = Some(Boundaries::new());
+        assert_eq!(json(&round_trip(&contents)), json(&contents));
+    }
+}
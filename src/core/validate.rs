@@ -0,0 +1,272 @@
+//! Consistency checks for an `ElectionStage` and its `ElectionResults`.
+use super::*;
+
+/// A problem found by `validate`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Issue {
+    /// An entity was added twice, see `StageBuilder`.
+    Duplicate(Entity, u32),
+    /// An entity was changed before being added, see `StageBuilder`.
+    Unknown(Entity, u32),
+    /// `from` refers to an entity that isn't in the stage.
+    Dangling { from: (Entity, u32), to: (Entity, u32) },
+    /// `district` isn't listed in `Area::districts` of its own area, or is listed in others.
+    AreaMismatch { district: DistrictID, area: AreaID, listed_in: Vec<AreaID> },
+    /// The results have an entry for an entity that isn't in the stage.
+    ResultsForUnknown(Entity, u32),
+    /// The results of `from` refer to an entity that isn't in the stage.
+    DanglingResults { from: (Entity, u32), to: (Entity, u32) },
+    /// The results have no entry for `district`.
+    MissingResults { district: DistrictID },
+    /// `candidate` has votes in `district` without standing there.
+    NotStanding { district: DistrictID, candidate: CandidateID },
+    /// `candidate` stands in `district`, which has candidate votes, but has no entry.
+    NoVotes { district: DistrictID, candidate: CandidateID },
+    /// `party` has party votes in `district`, but no candidates on the list they count for.
+    NoListCandidates { district: DistrictID, party: PartyID },
+    /// `area` has more seats of its own than list candidates to fill them.
+    UnfillableSeats { area: AreaID, seats: SeatCount, candidates: usize },
+}
+
+impl Issue {
+    /// Whether methods can't run correctly on the data. Too few list
+    /// candidates only leave seats unfilled, so that is a warning.
+    pub fn is_error(&self) -> bool {
+        !matches!(self, Issue::UnfillableSeats { .. })
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::Duplicate(entity, id) => write!(f, "{} {} is added more than once", entity, id),
+            Issue::Unknown(entity, id) => write!(f, "unknown {} {}", entity, id),
+            Issue::Dangling { from, to } => write!(f, "{} {} refers to unknown {} {}", from.0, from.1, to.0, to.1),
+            Issue::AreaMismatch { district, area, listed_in } =>
+                write!(f, "district {} is in area {}, but listed in areas {:?}", district, area, listed_in),
+            Issue::ResultsForUnknown(entity, id) => write!(f, "results for unknown {} {}", entity, id),
+            Issue::DanglingResults { from, to } => write!(f, "results of {} {} refer to unknown {} {}", from.0, from.1, to.0, to.1),
+            Issue::MissingResults { district } => write!(f, "district {} has no results", district),
+            Issue::NotStanding { district, candidate } => write!(f, "candidate {} has votes in district {} without standing there", candidate, district),
+            Issue::NoVotes { district, candidate } => write!(f, "candidate {} stands in district {} but has no votes entry", candidate, district),
+            Issue::NoListCandidates { district, party } => write!(f, "party {} has votes in district {} but no list candidates", party, district),
+            Issue::UnfillableSeats { area, seats, candidates } =>
+                write!(f, "area {} has {} seats of its own but only {} list candidates", area, seats, candidates),
+        }
+    }
+}
+
+/// Checks that the IDs in `stage` refer to each other consistently.
+pub fn validate_stage(stage: &ElectionStage) -> Result<(), Vec<Issue>> {
+    let mut issues = Vec::new();
+
+    let mut listed: HashMap<DistrictID, Vec<AreaID>> = HashMap::with_capacity(stage.districts.len());
+    for id in sorted::keys(&stage.areas) {
+        let area = &stage.areas[&id];
+        if let Some(parent) = area.parent.filter(|p| !stage.areas.contains_key(p)) {
+            issues.push(Issue::Dangling { from: (Entity::Area, id as u32), to: (Entity::Area, parent as u32) });
+        }
+        for district in sorted::items(&area.districts) {
+            if stage.districts.contains_key(&district) {
+                listed.entry(district).or_default().push(id);
+            } else {
+                issues.push(Issue::Dangling { from: (Entity::Area, id as u32), to: (Entity::District, district) });
+            }
+        }
+        for candidate in sorted::items(&area.candidates).into_iter().filter(|c| !stage.candidates.contains_key(c)) {
+            issues.push(Issue::Dangling { from: (Entity::Area, id as u32), to: (Entity::Candidate, candidate) });
+        }
+        if area.seats as usize > area.candidates.len() {
+            issues.push(Issue::UnfillableSeats { area: id, seats: area.seats, candidates: area.candidates.len() });
+        }
+    }
+
+    for id in sorted::keys(&stage.districts) {
+        let district = &stage.districts[&id];
+        if !stage.areas.contains_key(&district.area) {
+            issues.push(Issue::Dangling { from: (Entity::District, id), to: (Entity::Area, district.area as u32) });
+        } else {
+            let listed_in = listed.remove(&id).unwrap_or_default();
+            if listed_in != [district.area] {
+                issues.push(Issue::AreaMismatch { district: id, area: district.area, listed_in });
+            }
+        }
+        for candidate in sorted::items(&district.candidates).into_iter().filter(|c| !stage.candidates.contains_key(c)) {
+            issues.push(Issue::Dangling { from: (Entity::District, id), to: (Entity::Candidate, candidate) });
+        }
+    }
+
+    for id in sorted::keys(&stage.candidates) {
+        if let Some(party) = stage.candidates[&id].party.filter(|p| !stage.parties.contains_key(p)) {
+            issues.push(Issue::Dangling { from: (Entity::Candidate, id), to: (Entity::Party, party as u32) });
+        }
+    }
+
+    for id in sorted::keys(&stage.units) {
+        let district = stage.units[&id].district;
+        if !stage.districts.contains_key(&district) {
+            issues.push(Issue::Dangling { from: (Entity::Unit, id), to: (Entity::District, district) });
+        }
+    }
+
+    if issues.is_empty() { Ok(()) } else { Err(issues) }
+}
+
+/// Checks `stage` with `validate_stage`, and that `results` match it.
+pub fn validate(stage: &ElectionStage, results: &ElectionResults) -> Result<(), Vec<Issue>> {
+    let mut issues = validate_stage(stage).err().unwrap_or_default();
+
+    for id in sorted::keys(&stage.districts) {
+        if !results.districts.contains_key(&id) {
+            issues.push(Issue::MissingResults { district: id });
+        }
+    }
+
+    for id in sorted::keys(&results.districts) {
+        let dres = &results.districts[&id];
+        let district = match stage.districts.get(&id) {
+            Some(district) => district,
+            None => {
+                issues.push(Issue::ResultsForUnknown(Entity::District, id));
+                continue
+            }
+        };
+
+        for candidate in sorted::keys(&dres.candidate_votes) {
+            if !stage.candidates.contains_key(&candidate) {
+                issues.push(Issue::DanglingResults { from: (Entity::District, id), to: (Entity::Candidate, candidate) });
+            } else if !district.candidates.contains(&candidate) {
+                issues.push(Issue::NotStanding { district: id, candidate });
+            }
+        }
+        if !dres.candidate_votes.is_empty() {
+            // Candidates of other elections on the stage have no votes in this one,
+            // and dangling IDs have already been reported.
+            let standing = |c: &CandidateID| stage.candidates.get(c).filter(|c| c.stands_in(results.date)).is_some();
+            for candidate in sorted::items(&district.candidates).into_iter().filter(|c| standing(c) && !dres.candidate_votes.contains_key(c)) {
+                issues.push(Issue::NoVotes { district: id, candidate });
+            }
+        }
+
+        for party in sorted::keys(&dres.party_votes) {
+            if !stage.parties.contains_key(&party) {
+                issues.push(Issue::DanglingResults { from: (Entity::District, id), to: (Entity::Party, party as u32) });
+            } else if dres.party_votes[&party] > 0 {
+                // Dangling IDs on the way have already been reported.
+                if utils::party_list(stage, party, dres.party_list_source, id, results.date).map(|list| list.is_empty()).unwrap_or(false) {
+                    issues.push(Issue::NoListCandidates { district: id, party });
+                }
+            }
+        }
+    }
+
+    for id in sorted::keys(&results.units) {
+        if !stage.units.contains_key(&id) {
+            issues.push(Issue::ResultsForUnknown(Entity::Unit, id));
+        }
+        for candidate in sorted::keys(&results.units[&id].candidate_votes).into_iter().filter(|c| !stage.candidates.contains_key(c)) {
+            issues.push(Issue::DanglingResults { from: (Entity::Unit, id), to: (Entity::Candidate, candidate) });
+        }
+    }
+
+    if issues.is_empty() { Ok(()) } else { Err(issues) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Districts 1 and 2 in area 1, where parties 0 and 1 have candidates
+    /// `10 * district + party`, and party 0 has list candidate 30.
+    fn stage() -> ElectionStage {
+        let mut builder = ElectionStage::builder();
+        builder.area(1, "Area", None);
+        for party in 0..2 {
+            builder.party(party, Party { name: format!("Party {}", party), color: 0, type_: PartyType::Other, abbreviation: None, names: HashMap::new() });
+        }
+        for district in 1..=2 {
+            builder.district(district, format!("District {}", district), 1, 1);
+            for party in 0..2 {
+                builder.candidate(10 * district + party as CandidateID, None, Some(party), district);
+            }
+        }
+        builder.list_candidate(30, None, 0, 1).build().unwrap()
+    }
+
+    fn dres(candidate_votes: &[(CandidateID, u32)]) -> DistrictResults {
+        DistrictResults {
+            party_votes: HashMap::new(),
+            party_list_source: PartyListSource::Area,
+            candidate_votes: candidate_votes.iter().copied().collect(),
+            electorate: None,
+            ballots: None,
+            invalid: None,
+            invalid_party: None,
+        }
+    }
+
+    /// Results for `stage()`, with votes for every candidate.
+    fn results() -> ElectionResults {
+        let districts = (1..=2).map(|d| (d, dres(&[(10 * d, 20), (10 * d + 1, 10)]))).collect();
+        ElectionResults { districts, date: Date::new(2020, 1, 1), units: HashMap::new() }
+    }
+
+    #[test]
+    fn consistent_data_has_no_issues() {
+        assert_eq!(validate(&stage(), &results()), Ok(()));
+    }
+
+    #[test]
+    fn stage_ids_must_refer_to_each_other() {
+        let mut stage = stage();
+        stage.areas.get_mut(&1).unwrap().districts.remove(&2);
+        stage.districts.get_mut(&2).unwrap().candidates.insert(99);
+        stage.candidates.get_mut(&10).unwrap().party = Some(5);
+        let issues = validate_stage(&stage).unwrap_err();
+        assert_eq!(issues, vec![
+            Issue::AreaMismatch { district: 2, area: 1, listed_in: vec![] },
+            Issue::Dangling { from: (Entity::District, 2), to: (Entity::Candidate, 99) },
+            Issue::Dangling { from: (Entity::Candidate, 10), to: (Entity::Party, 5) },
+        ]);
+        assert!(issues.iter().all(Issue::is_error));
+    }
+
+    #[test]
+    fn too_few_list_candidates_is_a_warning() {
+        let mut stage = stage();
+        stage.areas.get_mut(&1).unwrap().seats = 2;
+        let issues = validate(&stage, &results()).unwrap_err();
+        assert_eq!(issues, vec![Issue::UnfillableSeats { area: 1, seats: 2, candidates: 1 }]);
+        assert!(!issues[0].is_error());
+    }
+
+    #[test]
+    fn results_must_match_the_stage() {
+        let stage = stage();
+        let mut results = results();
+        results.districts.remove(&2);
+        results.districts.insert(9, dres(&[]));
+        results.units.insert(4, dres(&[(98, 1)]));
+        let district = results.districts.get_mut(&1).unwrap();
+        district.candidate_votes.remove(&11);
+        district.candidate_votes.insert(20, 5);
+        district.candidate_votes.insert(99, 5);
+        // Party 1 has no list candidates in area 1.
+        district.party_votes.insert(1, 5);
+        district.party_votes.insert(7, 5);
+
+        let issues = validate(&stage, &results).unwrap_err();
+        assert_eq!(issues, vec![
+            Issue::MissingResults { district: 2 },
+            Issue::NotStanding { district: 1, candidate: 20 },
+            Issue::DanglingResults { from: (Entity::District, 1), to: (Entity::Candidate, 99) },
+            Issue::NoVotes { district: 1, candidate: 11 },
+            Issue::NoListCandidates { district: 1, party: 1 },
+            Issue::DanglingResults { from: (Entity::District, 1), to: (Entity::Party, 7) },
+            Issue::ResultsForUnknown(Entity::District, 9),
+            Issue::ResultsForUnknown(Entity::Unit, 4),
+            Issue::DanglingResults { from: (Entity::Unit, 4), to: (Entity::Candidate, 98) },
+        ]);
+        assert!(issues.iter().all(Issue::is_error));
+    }
+}
@@ -0,0 +1,377 @@
+//! Generation of `Grouping`s from the districts of each `Area`.
+use crate::core::*;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// Number of seeded attempts per area when adjacency is known.
+/// The most contiguous and best balanced attempt is kept.
+const ATTEMPTS: u64 = 16;
+
+/// Small deterministic random number generator (SplitMix64).
+pub(crate) struct Rng(pub u64);
+
+impl Rng {
+    pub fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform number in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Weight of some results when balancing groups or districts. Uses the
+/// electorate, or the number of votes cast if it isn't known.
+pub fn results_weight(results: &DistrictResults) -> u64 {
+    if let Some(electorate) = results.electorate {
+        return electorate as u64;
+    }
+    let candidate_votes: u64 = results.candidate_votes.values().map(|&v| v as u64).sum();
+    if candidate_votes > 0 { candidate_votes } else { results.party_votes.values().map(|&v| v as u64).sum() }
+}
+
+/// Weight of a district when balancing groups. See `results_weight`.
+pub fn district_weight(results: &ElectionResults, district: DistrictID) -> u64 {
+    results.districts.get(&district).map(results_weight).unwrap_or(0)
+}
+
+/// Sizes of the groups to split `n` districts into.
+fn group_sizes(n: usize, size: GroupingSize) -> Vec<usize> {
+    if n == 0 {
+        return Vec::new();
+    }
+
+    match size {
+        GroupingSize::Exact(size) => {
+            let size = (size as usize).max(1);
+            let mut sizes = vec![size; n / size];
+            if !n.is_multiple_of(size) {
+                sizes.push(n % size);
+            }
+            sizes
+        },
+        GroupingSize::Range { min, max } => {
+            let (min, max) = ((min as usize).max(1), (max as usize).max(1));
+            let target = size.preferred() as f64;
+            let fewest = n.div_ceil(max);
+            let most = (n / min).max(fewest);
+            let count = (fewest..=most)
+                .min_by(|&a, &b| (n as f64 / a as f64 - target).abs().partial_cmp(&(n as f64 / b as f64 - target).abs()).unwrap())
+                .unwrap();
+            (0..count).map(|i| n / count + if i < n % count { 1 } else { 0 }).collect()
+        },
+    }
+}
+
+/// Splits each `Area`'s districts into groups of `size` districts.
+///
+/// If the stage has an adjacency graph, groups are grown from a seed district
+/// into neighbouring districts, so that they are contiguous where possible and
+/// have similar weights (see `district_weight`). Otherwise districts are grouped in ID order.
+/// The result only depends on the inputs and `seed`.
+pub fn generate(stage: &ElectionStage, results: &ElectionResults, size: GroupingSize, seed: u64) -> Grouping {
+    let mut areas: Vec<AreaID> = stage.areas.keys().copied().collect();
+    areas.sort();
+
+    let mut groups = Vec::new();
+    for area in areas {
+        let mut districts: Vec<DistrictID> = stage.areas[&area].districts.iter().copied().collect();
+        districts.sort();
+        let sizes = group_sizes(districts.len(), size);
+
+        match &stage.adjacency {
+            Some(adjacency) => {
+                let weights: HashMap<DistrictID, u64> = districts.iter().map(|&d| (d, district_weight(results, d))).collect();
+                let best = (0..ATTEMPTS)
+                    .map(|attempt| grow(adjacency, &districts, &sizes, &weights, &mut Rng(seed ^ ((area as u64) << 32) ^ attempt)))
+                    .min_by(|a, b| a.0.cmp(&b.0).then(a.1.partial_cmp(&b.1).unwrap()))
+                    .unwrap();
+                groups.extend(best.2);
+            },
+            None => {
+                let mut rest = &districts[..];
+                for &size in sizes.iter() {
+                    groups.push(rest[..size].iter().copied().collect());
+                    rest = &rest[size..];
+                }
+            }
+        }
+    }
+
+    Grouping(groups)
+}
+
+/// Grows groups with the given sizes over the adjacency graph.
+/// Returns the number of non-contiguous groups, the spread of group weights
+/// relative to their targets, and the groups.
+fn grow(adjacency: &Adjacency, districts: &[DistrictID], sizes: &[usize], weights: &HashMap<DistrictID, u64>, rng: &mut Rng) -> (usize, f64, Vec<HashSet<DistrictID>>) {
+    let total_weight: u64 = districts.iter().map(|d| weights[d]).sum();
+    let mut unassigned: HashSet<DistrictID> = districts.iter().copied().collect();
+    let mut groups = Vec::with_capacity(sizes.len());
+    let mut broken = 0;
+    let mut spread = 0.0;
+
+    for &size in sizes.iter() {
+        let target = total_weight as f64 * size as f64 / districts.len() as f64;
+        let free_neighbours = |d: DistrictID, unassigned: &HashSet<DistrictID>| adjacency.neighbours(d).filter(|n| unassigned.contains(n)).count();
+
+        // Start at the district with the fewest free neighbours, so that
+        // districts at the edge of what's left aren't cut off.
+        let mut candidates: Vec<DistrictID> = unassigned.iter().copied().collect();
+        candidates.sort();
+        let fewest = candidates.iter().map(|&d| free_neighbours(d, &unassigned)).min().unwrap();
+        candidates.retain(|&d| free_neighbours(d, &unassigned) == fewest);
+        let start = candidates[(rng.next() % candidates.len() as u64) as usize];
+
+        let mut group = HashSet::with_capacity(size);
+        let mut weight = 0u64;
+        let mut contiguous = true;
+        let mut next = Some(start);
+        while let Some(district) = next {
+            unassigned.remove(&district);
+            group.insert(district);
+            weight += weights[&district];
+            if group.len() == size {
+                break
+            }
+
+            let mut frontier: Vec<DistrictID> = group.iter().flat_map(|&d| adjacency.neighbours(d)).filter(|n| unassigned.contains(n)).collect();
+            frontier.sort();
+            frontier.dedup();
+
+            let remaining = (size - group.len()) as f64;
+            let want = (target - weight as f64) / remaining;
+            next = frontier.iter().copied()
+                .min_by(|a, b| (weights[a] as f64 - want).abs().partial_cmp(&(weights[b] as f64 - want).abs()).unwrap())
+                .or_else(|| {
+                    contiguous = false;
+                    districts.iter().copied().find(|d| unassigned.contains(d))
+                });
+        }
+
+        if !contiguous {
+            broken += 1;
+        }
+        if target > 0.0 {
+            spread += (weight as f64 - target).abs() / target;
+        }
+        groups.push(group);
+    }
+
+    (broken, spread, groups)
+}
+
+/// A problem found by `validate`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Issue {
+    /// `group` contains no districts.
+    Empty { group: usize },
+    /// `group` refers to a district that isn't in the stage.
+    UnknownDistrict { group: usize, district: DistrictID },
+    /// `district` isn't in any group.
+    Uncovered { district: DistrictID },
+    /// `district` is in more than one group.
+    Overlap { district: DistrictID, groups: Vec<usize> },
+    /// `group` contains districts from more than one `Area`.
+    CrossArea { group: usize, areas: Vec<AreaID> },
+    /// The districts of `group` don't form one connected piece.
+    /// Only checked if the stage has an adjacency graph.
+    NonContiguous { group: usize },
+}
+
+impl Issue {
+    /// Whether methods can't run correctly on the grouping.
+    /// Non-contiguous groups are only a warning, since some
+    /// districts, like islands, can't be grouped contiguously.
+    pub fn is_error(&self) -> bool {
+        !matches!(self, Issue::NonContiguous { .. })
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::Empty { group } => write!(f, "group {} is empty", group),
+            Issue::UnknownDistrict { group, district } => write!(f, "group {} contains unknown district {}", group, district),
+            Issue::Uncovered { district } => write!(f, "district {} is not in any group", district),
+            Issue::Overlap { district, groups } => write!(f, "district {} is in groups {:?}", district, groups),
+            Issue::CrossArea { group, areas } => write!(f, "group {} spans areas {:?}", group, areas),
+            Issue::NonContiguous { group } => write!(f, "group {} is not contiguous", group),
+        }
+    }
+}
+
+/// Checks that `grouping` covers every district of `stage` exactly once,
+/// with each group inside one `Area`.
+pub fn validate(grouping: &Grouping, stage: &ElectionStage) -> Result<(), Vec<Issue>> {
+    let mut issues = Vec::new();
+    let mut owners: HashMap<DistrictID, Vec<usize>> = HashMap::with_capacity(stage.districts.len());
+
+    for (gid, group) in grouping.iter() {
+        if group.is_empty() {
+            issues.push(Issue::Empty { group: gid });
+            continue
+        }
+
+        let mut districts: Vec<DistrictID> = group.iter().copied().collect();
+        districts.sort();
+
+        let mut areas = Vec::new();
+        for &district in districts.iter() {
+            owners.entry(district).or_default().push(gid);
+            match stage.districts.get(&district) {
+                Some(d) => if !areas.contains(&d.area) { areas.push(d.area) },
+                None => issues.push(Issue::UnknownDistrict { group: gid, district }),
+            }
+        }
+        if areas.len() > 1 {
+            areas.sort();
+            issues.push(Issue::CrossArea { group: gid, areas });
+        }
+
+        if let Some(adjacency) = &stage.adjacency {
+            let mut seen = HashSet::with_capacity(group.len());
+            let mut stack = vec![districts[0]];
+            while let Some(district) = stack.pop() {
+                if seen.insert(district) {
+                    stack.extend(adjacency.neighbours(district).filter(|d| group.contains(d)));
+                }
+            }
+            if seen.len() != group.len() {
+                issues.push(Issue::NonContiguous { group: gid });
+            }
+        }
+    }
+
+    let mut districts: Vec<DistrictID> = stage.districts.keys().copied().collect();
+    districts.sort();
+    for district in districts {
+        match owners.get(&district) {
+            None => issues.push(Issue::Uncovered { district }),
+            Some(groups) if groups.len() > 1 => issues.push(Issue::Overlap { district, groups: groups.clone() }),
+            _ => {}
+        }
+    }
+
+    if issues.is_empty() { Ok(()) } else { Err(issues) }
+}
+
+/// Validates every grouping. Returns a readable description of the first few
+/// errors if there are any, and otherwise descriptions of any warnings.
+pub fn validate_all(groupings: &Groupings, stage: &ElectionStage) -> Result<Vec<String>, Error> {
+    let mut sizes: Vec<u32> = groupings.keys().copied().collect();
+    sizes.sort();
+
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+    for size in sizes {
+        if let Err(issues) = validate(&groupings[&size], stage) {
+            let (e, w): (Vec<Issue>, Vec<Issue>) = issues.into_iter().partition(Issue::is_error);
+            for (issues, lines) in [(e, &mut errors), (w, &mut warnings)].iter_mut() {
+                lines.extend(issues.iter().take(10).map(|issue| format!("grouping {}: {}", size, issue)));
+                if issues.len() > 10 {
+                    lines.push(format!("grouping {}: and {} more", size, issues.len() - 10));
+                }
+            }
+        }
+    }
+
+    if errors.is_empty() { Ok(warnings) } else { Err(Error::Inconsistent(format!("invalid groupings:\n{}", errors.join("\n")))) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Districts `1..=n` of area 1 followed by those of area 2, for each `n`
+    /// in `areas`, with electorate `10 * id`. With `adjacent`, each district
+    /// borders the next one in its area.
+    fn stage(areas: &[DistrictID], adjacent: bool) -> (ElectionStage, ElectionResults) {
+        let mut builder = ElectionStage::builder();
+        let mut adjacency = Adjacency::new();
+        let mut districts = HashMap::new();
+        let mut id = 0;
+        for (i, &n) in areas.iter().enumerate() {
+            let area = i as AreaID + 1;
+            builder.area(area, format!("Area {}", area), None);
+            for index in 0..n {
+                id += 1;
+                builder.district(id, format!("District {}", id), area, 1);
+                if index > 0 {
+                    adjacency.add(id - 1, id, None);
+                }
+                districts.insert(id, DistrictResults {
+                    party_votes: HashMap::new(),
+                    party_list_source: PartyListSource::District,
+                    candidate_votes: HashMap::new(),
+                    electorate: Some(10 * id),
+                    ballots: None,
+                    invalid: None,
+                    invalid_party: None,
+                });
+            }
+        }
+        if adjacent {
+            builder.adjacency(adjacency);
+        }
+        (builder.build().unwrap(), ElectionResults { districts, date: Date::new(2020, 1, 1), units: HashMap::new() })
+    }
+
+    /// The groups of `grouping` as sorted lists, in order.
+    fn groups(grouping: &Grouping) -> Vec<Vec<DistrictID>> {
+        grouping.iter().map(|(_, group)| sorted::items(group)).collect()
+    }
+
+    #[test]
+    fn same_seed_gives_same_grouping() {
+        let (stage, results) = stage(&[7, 5], true);
+        let grouping = generate(&stage, &results, GroupingSize::Exact(2), 42);
+        assert_eq!(groups(&grouping), groups(&generate(&stage, &results, GroupingSize::Exact(2), 42)));
+
+        // A line of districts can always be grouped contiguously.
+        assert_eq!(validate(&grouping, &stage), Ok(()));
+        assert!(GroupingSize::Exact(2).matches(&grouping, &stage));
+    }
+
+    #[test]
+    fn without_adjacency_districts_are_grouped_in_id_order() {
+        let (stage, results) = stage(&[5, 2], false);
+        let grouping = generate(&stage, &results, GroupingSize::Exact(2), 42);
+        assert_eq!(groups(&grouping), vec![vec![1, 2], vec![3, 4], vec![5], vec![6, 7]]);
+    }
+
+    #[test]
+    fn range_sizes_are_within_bounds() {
+        let size = GroupingSize::Range { min: 2, max: 4 };
+        for n in 2..40 {
+            let sizes = group_sizes(n, size);
+            assert_eq!(sizes.iter().sum::<usize>(), n);
+            assert!(sizes.iter().all(|&s| (2..=4).contains(&s)), "{} districts in groups of {:?}", n, sizes);
+        }
+
+        let (stage, results) = stage(&[11, 6], true);
+        let grouping = generate(&stage, &results, size, 7);
+        assert_eq!(validate(&grouping, &stage), Ok(()));
+        assert!(size.matches(&grouping, &stage));
+    }
+
+    #[test]
+    fn validate_reports_overlaps_gaps_and_groups_across_areas() {
+        let (stage, _) = stage(&[3, 2], false);
+        let grouping = Grouping(vec![
+            [1, 2].iter().copied().collect(),
+            [2, 4].iter().copied().collect(),
+        ]);
+        assert_eq!(validate(&grouping, &stage), Err(vec![
+            Issue::CrossArea { group: 1, areas: vec![1, 2] },
+            Issue::Overlap { district: 2, groups: vec![0, 1] },
+            Issue::Uncovered { district: 3 },
+            Issue::Uncovered { district: 5 },
+        ]));
+    }
+}
@@ -1,3 +1,6 @@
 pub mod core;
 pub mod source;
 pub mod methods;
+pub mod apportionment;
+pub mod grouping;
+pub mod redistricting;
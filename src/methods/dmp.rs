@@ -1,20 +1,276 @@
 use crate::core::*;
+use crate::apportionment;
+use std::collections::{HashMap, HashSet};
 
+/// Dual-member proportional. The pairs of districts of the size 2 grouping are
+/// dual-member districts, whose first seat goes to the candidate with the most
+/// votes. The other seats make the result proportional in each area at
+/// `threshold_level` among the parties over `threshold` there: they go to the
+/// parties that still need seats, in the districts where they did best as a
+/// share of the vote.
 #[derive(Clone)]
 pub struct DMP {
     pub threshold: f32,
+    /// Area level at which `threshold` applies and seats are made proportional.
+    /// See `ElectionStage::areas_at_level`.
+    pub threshold_level: usize,
+}
+
+/// A dual-member district.
+struct Pair {
+    area: AreaID,
+    seats: SeatCount,
+    /// Votes of each candidate, most first.
+    votes: Vec<(CandidateID, u32)>,
+    total: u64,
+}
+
+impl Pair {
+    /// The party's votes in the pair, as a share of all votes.
+    fn share(&self, stage: &ElectionStage, party: PartyID) -> f64 {
+        let votes: u64 = self.votes.iter()
+            .filter(|(c, _)| stage.candidates.get(c).and_then(|c| c.party) == Some(party))
+            .map(|&(_, v)| v as u64)
+            .sum();
+        if self.total == 0 { 0.0 } else { votes as f64 / self.total as f64 }
+    }
+}
+
+impl Default for DMP {
+    fn default() -> Self {
+        DMP { threshold: 0.05, threshold_level: 0 }
+    }
 }
 
 impl ElectoralMethod for DMP {
-    fn district_size(&self) -> u32 {
-        2
+    fn grouping_sizes(&self) -> Vec<GroupingSize> {
+        vec![GroupingSize::Exact(2)]
+    }
+
+    fn run(&self, stage: &ElectionStage, r: &ElectionResults, g: &[&Grouping]) -> Result<SeatResult, Error> {
+        let grouping = g.first().ok_or(Error::Method("DMP needs a grouping of district pairs".to_owned()))?;
+        let party_of = |candidate: CandidateID| stage.candidate(candidate).map(|c| c.party);
+
+        let mut pairs = Vec::with_capacity(grouping.0.len());
+        for (_, districts) in grouping.iter() {
+            let mut ids: Vec<DistrictID> = districts.iter().copied().collect();
+            ids.sort();
+            let first = *ids.first().ok_or(Error::Method("DMP needs district pairs, but a group is empty".to_owned()))?;
+            let mut pair = Pair { area: stage.area_at_level(stage.district(first)?.area, self.threshold_level), seats: 0, votes: Vec::new(), total: 0 };
+            for &id in ids.iter() {
+                pair.seats += stage.district(id)?.seats;
+                let dres = r.districts.get(&id).ok_or(Error::Inconsistent(format!("no results for district {}", id)))?;
+                if !dres.candidate_votes.is_empty() {
+                    pair.votes.extend(dres.candidate_votes.iter().map(|(&c, &v)| (c, v)));
+                } else {
+                    // With party votes only, the party's list stands in the district in order of ID.
+                    for (&party, &votes) in dres.party_votes.iter() {
+                        let mut candidates = utils::party_list(stage, party, dres.party_list_source, id, r.date)?;
+                        candidates.sort();
+                        pair.votes.extend(candidates.into_iter().take(2).map(|c| (c, votes)));
+                    }
+                }
+            }
+            pair.votes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+            pair.total = pair.votes.iter().map(|&(_, v)| v as u64).sum();
+            pairs.push(pair);
+        }
+
+        // First seats.
+        let mut elected: HashSet<CandidateID> = HashSet::new();
+        let mut free: Vec<SeatCount> = Vec::with_capacity(pairs.len());
+        for pair in pairs.iter() {
+            let winner = pair.votes.first().filter(|_| pair.seats > 0).map(|&(c, _)| c);
+            elected.extend(winner);
+            free.push(pair.seats - winner.map_or(0, |_| 1));
+        }
+
+        let eligible = utils::parties_over_threshold(stage, r, self.threshold as f64, self.threshold_level);
+        let mut areas: Vec<AreaID> = pairs.iter().map(|p| p.area).collect::<HashSet<_>>().into_iter().collect();
+        areas.sort();
+        for area in areas {
+            let no_parties = HashSet::new();
+            let parties = eligible.get(&area).unwrap_or(&no_parties);
+            let in_area: Vec<usize> = (0..pairs.len()).filter(|&i| pairs[i].area == area).collect();
+
+            let mut votes: HashMap<PartyID, u32> = HashMap::new();
+            let mut first_seats: HashMap<PartyID, SeatCount> = HashMap::new();
+            let mut seats: SeatCount = 0;
+            for &i in in_area.iter() {
+                seats += pairs[i].seats;
+                for &(candidate, v) in pairs[i].votes.iter() {
+                    match party_of(candidate)? {
+                        Some(party) if parties.contains(&party) => {
+                            *votes.entry(party).or_insert(0) += v;
+                            if elected.contains(&candidate) {
+                                *first_seats.entry(party).or_insert(0) += 1;
+                            }
+                        },
+                        // Seats of independents and parties under the threshold aren't shared out.
+                        _ => if elected.contains(&candidate) {
+                            seats = seats.saturating_sub(1);
+                        },
+                    }
+                }
+            }
+
+            // Parties with more first seats than their share keep them, and
+            // the other seats are shared out again without them.
+            let mut kept: HashMap<PartyID, SeatCount> = HashMap::new();
+            let entitled = loop {
+                // Party IDs stand in for area IDs to share the seats by largest remainder.
+                let pool: HashMap<AreaID, u32> = votes.iter().filter(|(p, _)| !kept.contains_key(p)).map(|(&p, &v)| (p as AreaID, v)).collect();
+                let shares = apportionment::apportion(&apportionment::Method::Hamilton, &pool, seats);
+                let over: Vec<PartyID> = shares.iter()
+                    .map(|(&p, &s)| (p as PartyID, s))
+                    .filter(|(p, s)| first_seats.get(p) > Some(s))
+                    .map(|(p, _)| p)
+                    .collect();
+                if over.is_empty() {
+                    break shares.into_iter().map(|(p, s)| (p as PartyID, s)).collect::<HashMap<PartyID, SeatCount>>();
+                }
+                for party in over {
+                    kept.insert(party, first_seats[&party]);
+                    seats = seats.saturating_sub(first_seats[&party]);
+                }
+            };
+            let mut needed: HashMap<PartyID, SeatCount> = entitled.iter()
+                .map(|(p, &s)| (*p, s.saturating_sub(first_seats.get(p).map_or(0, |&x| x))))
+                .collect();
+
+            // Second seats go to the party's best candidate left in the pair
+            // where it has the largest share of the vote.
+            let mut options: Vec<(f64, usize, PartyID)> = Vec::new();
+            for &i in in_area.iter() {
+                for &party in needed.keys() {
+                    options.push((pairs[i].share(stage, party), i, party));
+                }
+            }
+            options.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap().then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+            for (_, i, party) in options {
+                if free[i] == 0 || needed[&party] == 0 {
+                    continue
+                }
+                let mut candidate = None;
+                for &(c, _) in pairs[i].votes.iter() {
+                    if !elected.contains(&c) && party_of(c)? == Some(party) {
+                        candidate = Some(c);
+                        break
+                    }
+                }
+                if let Some(candidate) = candidate {
+                    elected.insert(candidate);
+                    free[i] -= 1;
+                    *needed.get_mut(&party).unwrap() -= 1;
+                }
+            }
+
+            // Seats that no party could fill go to the runners-up.
+            for &i in in_area.iter() {
+                let runners_up: Vec<CandidateID> = pairs[i].votes.iter().map(|&(c, _)| c).filter(|c| !elected.contains(c)).take(free[i] as usize).collect();
+                free[i] -= runners_up.len() as SeatCount;
+                elected.extend(runners_up);
+            }
+        }
+
+        Ok(SeatResult {
+            seats: elected
+        })
+    }
+
+    fn param_specs(&self) -> Vec<ParamSpec> {
+        vec![ParamSpec {
+            name: "threshold",
+            description: "Share of the vote a party needs to win list seats",
+            type_: ParamType::Float { min: 0.0, max: 1.0 },
+            default: ParamValue::Float(0.05),
+        }, ParamSpec {
+            name: "threshold_level",
+            description: "Area level at which the threshold applies and seats are made proportional; 0 is the outermost level",
+            type_: ParamType::Int { min: 0, max: 9 },
+            default: ParamValue::Int(0),
+        }]
+    }
+
+    fn params(&self) -> Params {
+        let mut params = Params::new();
+        params.insert("threshold".to_owned(), ParamValue::Float(self.threshold as f64));
+        params.insert("threshold_level".to_owned(), ParamValue::Int(self.threshold_level as i64));
+        params
     }
 
-    fn run(&self, stage: &ElectionStage, r: &ElectionResults, g: &Grouping) -> Result<SeatResult, String> {
-        Err("todo".to_owned())
+    fn set_param(&mut self, name: &str, value: ParamValue) -> Result<(), Error> {
+        match name {
+            "threshold" => self.threshold = value.as_f64().ok_or(Error::Method("threshold must be a number".into()))? as f32,
+            "threshold_level" => self.threshold_level = value.as_i64().ok_or(Error::Method("threshold_level must be an integer".into()))? as usize,
+            _ => return Err(Error::Method(format!("unknown parameter {}", name)))
+        }
+        Ok(())
+    }
+
+    fn id(&self) -> &'static str {
+        "dmp"
     }
 
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two pairs of districts with candidates of parties 0, 1 and 2, whose
+    /// candidate in district `d` has ID `10 * d + party`.
+    fn election(votes: [[u32; 3]; 4]) -> (ElectionStage, ElectionResults, Grouping) {
+        let mut builder = ElectionStage::builder();
+        builder.area(1, "Area", None);
+        for party in 0..3 {
+            builder.party(party, Party { name: format!("Party {}", party), color: 0, type_: PartyType::Other, abbreviation: None, names: HashMap::new() });
+        }
+        let mut districts = HashMap::new();
+        for (i, votes) in votes.iter().enumerate() {
+            let id = i as DistrictID + 1;
+            builder.district(id, format!("District {}", id), 1, 1);
+            for party in 0..3 {
+                builder.candidate(10 * id + party as CandidateID, None, Some(party), id);
+            }
+            districts.insert(id, DistrictResults {
+                party_votes: HashMap::new(),
+                party_list_source: PartyListSource::District,
+                candidate_votes: (0..3).map(|party| (10 * id + party as CandidateID, votes[party])).collect(),
+                electorate: None,
+                ballots: None,
+                invalid: None,
+                invalid_party: None,
+            });
+        }
+        let stage = builder.build().unwrap();
+        let results = ElectionResults { districts, date: Date::new(2020, 1, 1), units: HashMap::new() };
+        let pairs = Grouping(vec![[1, 2].iter().copied().collect(), [3, 4].iter().copied().collect()]);
+        (stage, results, pairs)
+    }
+
+    fn seats(threshold: f32, (stage, results, pairs): &(ElectionStage, ElectionResults, Grouping)) -> Vec<CandidateID> {
+        let dmp = DMP { threshold, threshold_level: 0 };
+        let mut seats: Vec<CandidateID> = dmp.run(stage, results, &[pairs]).unwrap().seats.into_iter().collect();
+        seats.sort();
+        seats
+    }
+
+    #[test]
+    fn second_seats_are_proportional() {
+        // 210, 150 and 100 votes share 4 seats as 2, 1 and 1. Party 0 won both
+        // first seats, party 1 takes the pair where it has the larger share, and
+        // party 2 the other.
+        let election = election([[60, 30, 25], [55, 35, 25], [50, 40, 25], [45, 45, 25]]);
+        assert_eq!(seats(0.05, &election), vec![10, 12, 30, 41]);
+    }
+
+    #[test]
+    fn threshold_leaves_out_small_parties() {
+        // Party 2 has 22% of the vote, so its seat goes to party 1.
+        let election = election([[60, 30, 25], [55, 35, 25], [50, 40, 25], [45, 45, 25]]);
+        assert_eq!(seats(0.25, &election), vec![10, 21, 30, 41]);
+    }
+}
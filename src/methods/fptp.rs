@@ -6,22 +6,30 @@ use std::iter::Iterator;
 pub struct FPTP;
 
 impl ElectoralMethod for FPTP {
+    fn id(&self) -> &'static str {
+        "fptp"
+    }
+
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
 
-    fn district_size(&self) -> u32 {
-        1
+    fn grouping_sizes(&self) -> Vec<GroupingSize> {
+        vec![GroupingSize::Exact(1)]
     }
 
-    fn run(&self, stage: &ElectionStage, r: &ElectionResults, groupings: &Grouping) -> Result<SeatResult, String> {
+    fn run(&self, stage: &ElectionStage, r: &ElectionResults, groupings: &[&Grouping]) -> Result<SeatResult, Error> {
         let mut successful = HashSet::new();
-        for (gid, districts) in groupings.iter() {
-            let seats: SeatCount = districts.iter().map(|&id| stage.districts[&id].seats).sum();
+        let grouping = groupings.first().ok_or(Error::Method("FPTP needs a grouping of single districts".to_owned()))?;
+        for (_gid, districts) in grouping.iter() {
+            let mut seats: SeatCount = 0;
+            for &id in districts.iter() {
+                seats += stage.district(id)?.seats;
+            }
 
             let mut total_candidates_votes = Vec::new();
             for &district in districts.iter() {
-                let dres = &r.districts[&district];
+                let dres = r.districts.get(&district).ok_or(Error::Inconsistent(format!("no results for district {}", district)))?;
 
                 if !dres.candidate_votes.is_empty() { // District uses candidate votes
                     for (&candidate, &votes) in dres.candidate_votes.iter() {
@@ -30,9 +38,9 @@ impl ElectoralMethod for FPTP {
                 } else { // District only has party votes. In this case, we use the first candidate in the party list.
                     let source = dres.party_list_source;
                     for (&party, &votes) in dres.party_votes.iter() {
-                        let mut candidates: Vec<CandidateID> = utils::party_list(stage, party, source, district).collect();
+                        let mut candidates: Vec<CandidateID> = utils::party_list(stage, party, source, district, r.date)?;
                         candidates.sort();
-                        total_candidates_votes.push((*candidates.get(0).ok_or(format!("party {} does not have any candidates on list", party))?, votes));
+                        total_candidates_votes.push((*candidates.first().ok_or(Error::Method(format!("party {} does not have any candidates on list", party)))?, votes));
                     }
                 }
             }
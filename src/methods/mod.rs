@@ -1,2 +1,67 @@
 pub mod fptp;
-pub mod dmp;
\ No newline at end of file
+pub mod dmp;
+
+use crate::core::*;
+use serde::{Serialize, Deserialize};
+
+/// Describes an `ElectoralMethod` available in this crate.
+pub struct MethodInfo {
+    /// Stable identifier, used in saved scenarios and on the command line.
+    pub id: &'static str,
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Real-world uses of the method.
+    pub examples: &'static [&'static str],
+    /// Creates the method with default parameters.
+    pub create: fn() -> Box<dyn ElectoralMethod>,
+}
+
+/// Every method in this crate.
+pub const METHODS: &[MethodInfo] = &[
+    MethodInfo {
+        id: "fptp",
+        name: "FPTP",
+        description: "First past the post. The candidate with the most votes in each district wins.",
+        examples: &["Canada", "United Kingdom", "United States House of Representatives"],
+        create: || Box::new(fptp::FPTP),
+    },
+    MethodInfo {
+        id: "dmp",
+        name: "DMP",
+        description: "Dual-member proportional. Each district elects two members: the local winner, \
+            and a second member chosen to make the result proportional across the area.",
+        examples: &[],
+        create: || Box::new(dmp::DMP::default()),
+    },
+];
+
+/// Finds a method by its `MethodInfo::id`.
+pub fn find(id: &str) -> Option<&'static MethodInfo> {
+    METHODS.iter().find(|info| info.id == id)
+}
+
+/// A method and its parameters, as stored in a saved scenario:
+/// `{"method": "dmp", "params": {"threshold": 0.05}}`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MethodConfig {
+    pub method: String,
+    #[serde(default)]
+    pub params: Params,
+}
+
+impl MethodConfig {
+    pub fn of(method: &dyn ElectoralMethod) -> MethodConfig {
+        MethodConfig {
+            method: method.id().to_owned(),
+            params: method.params(),
+        }
+    }
+
+    /// Creates the method, using defaults for missing parameters.
+    pub fn build(&self) -> Result<Box<dyn ElectoralMethod>, Error> {
+        let info = find(&self.method).ok_or(Error::Method(format!("unknown method {}", self.method)))?;
+        let mut method = (info.create)();
+        method.set_params(&self.params)?;
+        Ok(method)
+    }
+}
@@ -0,0 +1,597 @@
+//! Drawing new districts from sub-district `Unit`s.
+use crate::core::*;
+use crate::grouping::{Rng, results_weight};
+use std::collections::{HashMap, HashSet};
+
+pub mod transposition;
+
+/// Number of seeded attempts at the initial map of each area.
+/// The most contiguous and best balanced attempt is kept.
+const ATTEMPTS: u64 = 8;
+
+/// Settings for `redistrict`.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Number of districts to draw in each area. Areas that aren't
+    /// listed keep their current number of districts.
+    pub districts: HashMap<AreaID, usize>,
+
+    /// Largest allowed difference between the weight of a district and the
+    /// average in its area, relative to the average, e.g. `0.05` for ±5%.
+    /// Weights are electorates, or votes if those aren't known.
+    pub tolerance: f64,
+
+    /// Number of steps of the Markov chain run on each area after the
+    /// initial map is grown. Each step proposes moving a unit on a
+    /// district boundary into the neighbouring district.
+    pub steps: usize,
+
+    /// How strongly the chain prefers compact districts, measured by the
+    /// boundaries cut between units (by length, if known). `0` treats all
+    /// maps within the constraints alike.
+    pub compactness: f64,
+
+    pub seed: u64,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            districts: HashMap::new(),
+            tolerance: 0.05,
+            steps: 10000,
+            compactness: 1.0,
+            seed: 0,
+        }
+    }
+}
+
+/// Draws a new map from the stage's units and returns the stage and results
+/// for it. Needs `ElectionStage::units`, `ElectionStage::unit_adjacency` and
+/// `ElectionResults::units`.
+///
+/// Each area's units are first grown into districts of similar weight,
+/// then a seeded chain of boundary moves balances them within `tolerance`,
+/// keeping districts contiguous. The result only depends on the inputs and `options`.
+///
+/// Candidates of the same party in a new district are merged into one, see `assemble`.
+pub fn redistrict(stage: &ElectionStage, results: &ElectionResults, options: &Options) -> Result<(ElectionStage, ElectionResults), Error> {
+    let adjacency = stage.unit_adjacency.as_ref().ok_or(Error::Method("redistricting needs the adjacency of units".into()))?;
+    if stage.units.is_empty() {
+        return Err(Error::Method("redistricting needs sub-district units".into()));
+    }
+
+    let mut units_by_area: HashMap<AreaID, Vec<UnitID>> = HashMap::new();
+    for (&id, unit) in stage.units.iter() {
+        let district = stage.districts.get(&unit.district).ok_or(Error::Missing(Entity::District, unit.district))?;
+        units_by_area.entry(district.area).or_default().push(id);
+    }
+    let weights: HashMap<UnitID, u64> = stage.units.keys().map(|&id| (id, results.units.get(&id).map(results_weight).unwrap_or(0))).collect();
+
+    let mut areas: Vec<AreaID> = units_by_area.keys().copied().collect();
+    areas.sort();
+
+    let mut plan: HashMap<UnitID, DistrictID> = HashMap::with_capacity(stage.units.len());
+    let mut drafts: Vec<(DistrictID, District)> = Vec::new();
+    for area in areas {
+        let units = units_by_area.get_mut(&area).unwrap();
+        units.sort();
+
+        let old_districts = &stage.areas[&area].districts;
+        let n = options.districts.get(&area).copied().unwrap_or(old_districts.len()).max(1).min(units.len());
+        let old_seats: SeatCount = old_districts.iter().filter_map(|d| stage.districts.get(d)).map(|d| d.seats).sum();
+        let seats = ((old_seats as f64 / n as f64).round() as SeatCount).max(1);
+
+        let assignment = draw_area(&stage.areas[&area].name, adjacency, units, &weights, n, options, options.seed ^ ((area as u64) << 32))?;
+
+        // Number districts in order of their lowest unit.
+        let mut first_units: Vec<(UnitID, usize)> = Vec::with_capacity(n);
+        for &unit in units.iter() {
+            if !first_units.iter().any(|&(_, d)| d == assignment[&unit]) {
+                first_units.push((unit, assignment[&unit]));
+            }
+        }
+        for (i, &(_, index)) in first_units.iter().enumerate() {
+            let id = drafts.len() as DistrictID + 1;
+            drafts.push((id, District {
+                name: format!("{} {}", stage.areas[&area].name, i + 1),
+                seats,
+                area,
+                candidates: HashSet::new(),
+            }));
+            for &unit in units.iter().filter(|u| assignment[u] == index) {
+                plan.insert(unit, id);
+            }
+        }
+    }
+
+    let drafts = drafts.into_iter().map(|(id, district)| {
+        let mut units: Vec<UnitID> = plan.iter().filter(|(_, &d)| d == id).map(|(&u, _)| u).collect();
+        units.sort();
+        Draft {
+            id,
+            district,
+            parts: vec![units.iter().filter_map(|u| results.units.get(u)).map(|r| (r, 1.0)).collect()],
+        }
+    }).collect();
+    let (mut new_stage, new_results, candidate_map) = assemble(stage, &[results.date], drafts);
+    let mut new_results = new_results.into_iter().next().unwrap();
+
+    new_stage.units = stage.units.iter().map(|(&id, unit)| (id, Unit { name: unit.name.clone(), district: plan[&id] })).collect();
+    new_stage.unit_adjacency = Some(adjacency.clone());
+    new_results.units = results.units.iter().map(|(&id, r)| {
+        let mut r = r.clone();
+        r.candidate_votes = HashMap::with_capacity(r.candidate_votes.len());
+        for (candidate, &votes) in results.units[&id].candidate_votes.iter() {
+            if let Some(&new) = candidate_map.get(&(plan[&id], *candidate)) {
+                *r.candidate_votes.entry(new).or_insert(0) += votes;
+            }
+        }
+        (id, r)
+    }).collect();
+
+    let mut district_adjacency = Adjacency::new();
+    for (&a, neighbours) in adjacency.0.iter() {
+        for (&b, &length) in neighbours.iter() {
+            match (plan.get(&a), plan.get(&b)) {
+                (Some(&da), Some(&db)) if a < b && da != db => district_adjacency.add(da, db, length),
+                _ => {}
+            }
+        }
+    }
+    new_stage.adjacency = Some(district_adjacency);
+
+    Ok((new_stage, new_results))
+}
+
+/// Splits `units` of the area called `name` into `n` districts. Returns the index of each unit's district.
+fn draw_area(name: &str, adjacency: &Adjacency, units: &[UnitID], weights: &HashMap<UnitID, u64>, n: usize, options: &Options, seed: u64) -> Result<HashMap<UnitID, usize>, Error> {
+    let (_, _, mut assignment) = (0..ATTEMPTS)
+        .map(|attempt| grow(adjacency, units, weights, n, &mut Rng(seed ^ attempt)))
+        .min_by(|a, b| a.0.cmp(&b.0).then(a.1.partial_cmp(&b.1).unwrap()))
+        .unwrap();
+
+    let total: u64 = units.iter().map(|u| weights[u]).sum();
+    let average = total as f64 / n as f64;
+    let mut district_weights = vec![0u64; n];
+    let mut sizes = vec![0usize; n];
+    for &unit in units.iter() {
+        district_weights[assignment[&unit]] += weights[&unit];
+        sizes[assignment[&unit]] += 1;
+    }
+    let penalty = |weight: u64| if average > 0.0 { ((weight as f64 - average).abs() / average - options.tolerance).max(0.0) } else { 0.0 };
+
+    let in_area: HashSet<UnitID> = units.iter().copied().collect();
+    let mut edges: Vec<(UnitID, UnitID)> = units.iter()
+        .flat_map(|&a| adjacency.neighbours(a).map(move |b| (a, b)))
+        .filter(|&(a, b)| a < b && in_area.contains(&b))
+        .collect();
+    edges.sort();
+    let length = |a: UnitID, b: UnitID| adjacency.boundary_length(a, b).unwrap_or(1.0);
+    let mean_length = if edges.is_empty() { 1.0 } else { edges.iter().map(|&(a, b)| length(a, b)).sum::<f64>() / edges.len() as f64 };
+
+    let mut rng = Rng(seed ^ 0x5bd1e995);
+    for _ in 0..if edges.is_empty() { 0 } else { options.steps } {
+        let (a, b) = edges[(rng.next() % edges.len() as u64) as usize];
+        let (unit, from, to) = if rng.next().is_multiple_of(2) { (a, assignment[&a], assignment[&b]) } else { (b, assignment[&b], assignment[&a]) };
+        if from == to || sizes[from] == 1 {
+            continue
+        }
+
+        let weight = weights[&unit];
+        let before = penalty(district_weights[from]) + penalty(district_weights[to]);
+        let after = penalty(district_weights[from] - weight) + penalty(district_weights[to] + weight);
+        if after > before {
+            continue
+        }
+
+        let cut_change: f64 = adjacency.neighbours(unit).filter(|n| in_area.contains(n)).map(|n| {
+            let district = assignment[&n];
+            length(unit, n) * ((district != to) as i32 - (district != from) as i32) as f64
+        }).sum();
+        if after == before && cut_change > 0.0 && rng.next_f64() >= (-options.compactness * cut_change / mean_length).exp() {
+            continue
+        }
+
+        if !stays_connected(adjacency, &assignment, unit, from) {
+            continue
+        }
+
+        assignment.insert(unit, to);
+        district_weights[from] -= weight;
+        district_weights[to] += weight;
+        sizes[from] -= 1;
+        sizes[to] += 1;
+    }
+
+    if district_weights.iter().any(|&w| penalty(w) > 0.0) {
+        return Err(Error::Method(format!("{}: could not balance districts within {}% of the average; try a larger tolerance or more steps", name, options.tolerance * 100.0)));
+    }
+    Ok(assignment)
+}
+
+/// Whether the neighbours of `unit` in district `from` stay connected to each other
+/// within the district when `unit` is taken out of it.
+fn stays_connected(adjacency: &Adjacency, assignment: &HashMap<UnitID, usize>, unit: UnitID, from: usize) -> bool {
+    let in_from = |u: &UnitID| *u != unit && assignment.get(u) == Some(&from);
+    let targets: HashSet<UnitID> = adjacency.neighbours(unit).filter(in_from).collect();
+    let start = match targets.iter().min() {
+        Some(&start) => start,
+        None => return true,
+    };
+
+    let mut seen = HashSet::new();
+    let mut found = 0;
+    let mut stack = vec![start];
+    while let Some(u) = stack.pop() {
+        if !seen.insert(u) {
+            continue
+        }
+        if targets.contains(&u) {
+            found += 1;
+            if found == targets.len() {
+                return true;
+            }
+        }
+        stack.extend(adjacency.neighbours(u).filter(in_from));
+    }
+    false
+}
+
+/// Moves the parts of each district that are cut off from its largest piece
+/// into the neighbouring district they share the most boundaries with.
+/// Returns the number of pieces still cut off, e.g. islands.
+fn merge_fragments(adjacency: &Adjacency, units: &[UnitID], assignment: &mut HashMap<UnitID, usize>) -> usize {
+    let pieces = |assignment: &HashMap<UnitID, usize>| -> Vec<Vec<UnitID>> {
+        let mut seen = HashSet::with_capacity(units.len());
+        let mut pieces = Vec::new();
+        for &start in units.iter() {
+            if seen.contains(&start) {
+                continue
+            }
+            let district = assignment[&start];
+            let mut piece = Vec::new();
+            let mut stack = vec![start];
+            while let Some(unit) = stack.pop() {
+                if !seen.insert(unit) {
+                    continue
+                }
+                piece.push(unit);
+                stack.extend(adjacency.neighbours(unit).filter(|n| assignment.get(n) == Some(&district)));
+            }
+            pieces.push(piece);
+        }
+        pieces
+    };
+
+    // Each pass merges every cut-off piece that has a neighbour; a few passes
+    // handle pieces that only touch other cut-off pieces.
+    for _ in 0..4 {
+        let pieces = pieces(assignment);
+        let mut largest: HashMap<usize, usize> = HashMap::new();
+        for (i, piece) in pieces.iter().enumerate() {
+            let district = assignment[&piece[0]];
+            let current = largest.entry(district).or_insert(i);
+            if piece.len() > pieces[*current].len() {
+                *current = i;
+            }
+        }
+
+        let mut moved = false;
+        for (i, piece) in pieces.iter().enumerate() {
+            let district = assignment[&piece[0]];
+            if largest[&district] == i {
+                continue
+            }
+
+            let mut shared: HashMap<usize, usize> = HashMap::new();
+            for &unit in piece.iter() {
+                for neighbour in adjacency.neighbours(unit) {
+                    match assignment.get(&neighbour) {
+                        Some(&other) if other != district => *shared.entry(other).or_insert(0) += 1,
+                        _ => {}
+                    }
+                }
+            }
+            if let Some((&target, _)) = shared.iter().max_by_key(|(&d, &count)| (count, std::cmp::Reverse(d))) {
+                for &unit in piece.iter() {
+                    assignment.insert(unit, target);
+                }
+                moved = true;
+            }
+        }
+        if !moved {
+            break
+        }
+    }
+
+    let mut districts_seen = HashSet::new();
+    pieces(assignment).iter().filter(|piece| !districts_seen.insert(assignment[&piece[0]])).count()
+}
+
+/// Grows `n` districts of similar weight from `units` over the adjacency graph.
+/// Returns the number of pieces cut off from their district, the largest deviation
+/// from the average weight, and the index of each unit's district.
+fn grow(adjacency: &Adjacency, units: &[UnitID], weights: &HashMap<UnitID, u64>, n: usize, rng: &mut Rng) -> (usize, f64, HashMap<UnitID, usize>) {
+    let mut unassigned: HashSet<UnitID> = units.iter().copied().collect();
+    let mut assignment: HashMap<UnitID, usize> = HashMap::with_capacity(units.len());
+
+    for index in 0..n {
+        if index == n - 1 {
+            for &unit in units.iter().filter(|u| unassigned.contains(u)) {
+                assignment.insert(unit, index);
+            }
+            break
+        }
+
+        let remaining_weight: u64 = unassigned.iter().map(|u| weights[u]).sum();
+        let target = remaining_weight as f64 / (n - index) as f64;
+        let free_neighbours = |u: UnitID| adjacency.neighbours(u).filter(|n| unassigned.contains(n)).count();
+
+        // Start at a unit with the fewest free neighbours, so that units
+        // at the edge of what's left aren't cut off.
+        let mut candidates: Vec<UnitID> = unassigned.iter().copied().collect();
+        candidates.sort();
+        let fewest = candidates.iter().map(|&u| free_neighbours(u)).min().unwrap();
+        candidates.retain(|&u| free_neighbours(u) == fewest);
+        let mut next = Some(candidates[(rng.next() % candidates.len() as u64) as usize]);
+
+        // Unassigned units next to the district, with how many of their neighbours are in it.
+        let mut frontier: HashMap<UnitID, usize> = HashMap::new();
+        let mut weight = 0u64;
+        while let Some(unit) = next {
+            unassigned.remove(&unit);
+            frontier.remove(&unit);
+            assignment.insert(unit, index);
+            weight += weights[&unit];
+            for neighbour in adjacency.neighbours(unit).filter(|n| unassigned.contains(n)) {
+                *frontier.entry(neighbour).or_insert(0) += 1;
+            }
+
+            // Leave at least one unit for each remaining district.
+            if weight as f64 >= target || unassigned.len() < n - index {
+                break
+            }
+
+            // Prefer units with the most neighbours in the district, which keeps it compact.
+            next = match frontier.values().max() {
+                Some(&most) => {
+                    let mut best: Vec<UnitID> = frontier.iter().filter(|(_, &c)| c == most).map(|(&u, _)| u).collect();
+                    best.sort();
+                    Some(best[(rng.next() % best.len() as u64) as usize])
+                },
+                None => units.iter().copied().find(|u| unassigned.contains(u)),
+            };
+
+            // Stop short if adding the unit would overshoot more than stopping undershoots.
+            if let Some(unit) = next {
+                if (weight + weights[&unit]) as f64 - target > target - weight as f64 {
+                    break
+                }
+            }
+        }
+    }
+
+    let broken = merge_fragments(adjacency, units, &mut assignment);
+
+    let total: u64 = units.iter().map(|u| weights[u]).sum();
+    let average = total as f64 / n as f64;
+    let mut district_weights = vec![0u64; n];
+    for (&unit, &index) in assignment.iter() {
+        district_weights[index] += weights[&unit];
+    }
+    let deviation = district_weights.iter().map(|&w| if average > 0.0 { (w as f64 - average).abs() / average } else { 0.0 }).fold(0.0, f64::max);
+
+    (broken, deviation, assignment)
+}
+
+/// A new district and, for each election, the results it's made of,
+/// each with the share of those results that falls inside it.
+pub(crate) struct Draft<'a> {
+    pub id: DistrictID,
+    pub district: District,
+    pub parts: Vec<Vec<(&'a DistrictResults, f64)>>,
+}
+
+/// Builds a stage, and results for each of `dates`, from new districts,
+/// keeping the areas, parties and area candidates of `stage`.
+///
+/// Votes are added up from the parts, weighted by their shares and rounded.
+/// In each new district, the candidates of one party are merged into one
+/// candidate, named only if they were all the same candidate; independents
+/// are kept apart. The candidates are shared by all elections, so that their
+/// results can be compared, and get 0 votes in those they didn't stand in.
+/// Also returns the new candidate for each district and old candidate.
+/// The new stage has no units or adjacency.
+pub(crate) fn assemble(stage: &ElectionStage, dates: &[Date], mut drafts: Vec<Draft>) -> (ElectionStage, Vec<ElectionResults>, HashMap<(DistrictID, CandidateID), CandidateID>) {
+    drafts.sort_by_key(|d| d.id);
+
+    let mut areas = stage.areas.clone();
+    for area in areas.values_mut() {
+        area.districts.clear();
+    }
+    let mut candidates: HashMap<CandidateID, Candidate> = areas.values()
+        .flat_map(|a| a.candidates.iter())
+        .filter_map(|id| stage.candidates.get(id).map(|c| (*id, c.clone())))
+        .collect();
+    let mut next_candidate = stage.candidates.keys().max().map(|&x| x + 1).unwrap_or(0);
+
+    let mut districts = HashMap::with_capacity(drafts.len());
+    let mut district_results: Vec<HashMap<DistrictID, DistrictResults>> = dates.iter().map(|_| HashMap::with_capacity(drafts.len())).collect();
+    let mut candidate_map = HashMap::new();
+    for Draft { id, mut district, parts } in drafts {
+        let mut candidate_votes: Vec<HashMap<CandidateID, f64>> = parts.iter().map(|_| HashMap::new()).collect();
+        let mut party_votes: Vec<HashMap<PartyID, f64>> = parts.iter().map(|_| HashMap::new()).collect();
+        for (i, election) in parts.iter().enumerate() {
+            for &(results, share) in election.iter() {
+                for (&candidate, &votes) in results.candidate_votes.iter() {
+                    *candidate_votes[i].entry(candidate).or_insert(0.0) += votes as f64 * share;
+                }
+                for (&party, &votes) in results.party_votes.iter() {
+                    *party_votes[i].entry(party).or_insert(0.0) += votes as f64 * share;
+                }
+            }
+        }
+
+        // Candidates are merged by party; independents are keyed by themselves.
+        let mut merged: HashMap<(Option<PartyID>, Option<CandidateID>), Vec<CandidateID>> = HashMap::new();
+        for &candidate in candidate_votes.iter().flat_map(|votes| votes.keys()) {
+            let party = stage.candidates.get(&candidate).and_then(|c| c.party);
+            let key = (party, if party.is_some() { None } else { Some(candidate) });
+            merged.entry(key).or_default().push(candidate);
+        }
+        let mut keys: Vec<_> = merged.keys().copied().collect();
+        keys.sort();
+
+        let mut votes: Vec<HashMap<CandidateID, u32>> = parts.iter().map(|_| HashMap::with_capacity(keys.len())).collect();
+        for key in keys {
+            let mut old = merged.remove(&key).unwrap();
+            old.sort();
+            old.dedup();
+
+            let new = next_candidate;
+            next_candidate += 1;
+            candidates.insert(new, Candidate {
+                name: if old.len() == 1 { stage.candidates.get(&old[0]).and_then(|c| c.name.clone()) } else { None },
+                party: key.0,
+                election: None,
+            });
+            district.candidates.insert(new);
+            for (votes, candidate_votes) in votes.iter_mut().zip(candidate_votes.iter()) {
+                if !candidate_votes.is_empty() {
+                    votes.insert(new, old.iter().filter_map(|c| candidate_votes.get(c)).sum::<f64>().round() as u32);
+                }
+            }
+            for candidate in old {
+                candidate_map.insert((id, candidate), new);
+            }
+        }
+
+        for (i, election) in parts.iter().enumerate() {
+            let count = |f: fn(&DistrictResults) -> Option<u32>| -> Option<u32> {
+                election.iter().map(|&(r, share)| f(r).map(|v| v as f64 * share)).sum::<Option<f64>>().map(|v| v.round() as u32)
+            };
+            district_results[i].insert(id, DistrictResults {
+                party_votes: party_votes[i].iter().map(|(&p, &v)| (p, v.round() as u32)).collect(),
+                party_list_source: election.first().map(|(r, _)| r.party_list_source).unwrap_or(PartyListSource::Area),
+                candidate_votes: std::mem::take(&mut votes[i]),
+                electorate: count(|r| r.electorate),
+                ballots: count(|r| r.ballots),
+                invalid: count(|r| r.invalid),
+                invalid_party: count(|r| r.invalid_party),
+            });
+        }
+
+        if let Some(area) = areas.get_mut(&district.area) {
+            area.districts.insert(id);
+        }
+        districts.insert(id, district);
+    }
+
+    (
+        ElectionStage {
+            districts,
+            candidates,
+            parties: stage.parties.clone(),
+            areas,
+            adjacency: None,
+            units: HashMap::new(),
+            unit_adjacency: None,
+        },
+        dates.iter().zip(district_results).map(|(&date, districts)| ElectionResults {
+            districts,
+            date,
+            units: HashMap::new(),
+        }).collect(),
+        candidate_map,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIDE: UnitID = 6;
+
+    /// A `SIDE` by `SIDE` grid of units, numbered by row, in two districts of
+    /// one area split down the middle. Each district has a candidate of party
+    /// 0 and 1, whose IDs are `2 * district + party`.
+    fn grid() -> (ElectionStage, ElectionResults) {
+        let mut builder = ElectionStage::builder();
+        builder.area(1, "Grid", None);
+        for party in 0..2 {
+            builder.party(party, Party { name: format!("Party {}", party), color: 0, type_: PartyType::Other, abbreviation: None, names: HashMap::new() });
+        }
+        for district in 0..2 {
+            builder.district(district, format!("District {}", district), 1, 1);
+            for party in 0..2 {
+                builder.candidate(2 * district + party, None, Some(party as PartyID), district);
+            }
+        }
+
+        let mut adjacency = Adjacency::new();
+        let mut units = HashMap::new();
+        for id in 0..SIDE * SIDE {
+            let (row, column) = (id / SIDE, id % SIDE);
+            let district = column * 2 / SIDE;
+            builder.unit(id, format!("Unit {}", id), district);
+            if column > 0 {
+                adjacency.add(id - 1, id, None);
+            }
+            if row > 0 {
+                adjacency.add(id - SIDE, id, None);
+            }
+            let electorate = 100 + id * 7 % 20;
+            units.insert(id, DistrictResults {
+                party_votes: HashMap::new(),
+                party_list_source: PartyListSource::District,
+                candidate_votes: (0..2).map(|party| (2 * district + party, electorate / (2 + party))).collect(),
+                electorate: Some(electorate),
+                ballots: None,
+                invalid: None,
+                invalid_party: None,
+            });
+        }
+        builder.unit_adjacency(adjacency);
+        (builder.build().unwrap(), ElectionResults { districts: HashMap::new(), date: Date::new(2020, 1, 1), units })
+    }
+
+    #[test]
+    fn grid_is_split_into_balanced_contiguous_districts() {
+        let (stage, results) = grid();
+        let options = Options {
+            districts: std::iter::once((1, 4)).collect(),
+            tolerance: 0.05,
+            steps: 2000,
+            compactness: 1.0,
+            seed: 3,
+        };
+        let (new_stage, new_results) = redistrict(&stage, &results, &options).unwrap();
+        assert_eq!(new_stage.districts.len(), 4);
+        assert_eq!(new_stage.units.len(), stage.units.len());
+
+        let adjacency = new_stage.unit_adjacency.as_ref().unwrap();
+        let total: u32 = results.units.values().filter_map(|r| r.electorate).sum();
+        let average = total as f64 / 4.0;
+        for id in sorted::keys(&new_stage.districts) {
+            let units: HashSet<UnitID> = new_stage.units_in(id).into_iter().collect();
+            let mut seen = HashSet::new();
+            let mut stack: Vec<UnitID> = units.iter().copied().take(1).collect();
+            while let Some(unit) = stack.pop() {
+                if seen.insert(unit) {
+                    stack.extend(adjacency.neighbours(unit).filter(|u| units.contains(u)));
+                }
+            }
+            assert_eq!(seen.len(), units.len(), "district {} is not contiguous", id);
+
+            let electorate = new_results.districts[&id].electorate.unwrap();
+            assert!((electorate as f64 - average).abs() <= average * options.tolerance, "district {} has {} of {}", id, electorate, average);
+        }
+        assert_eq!(validate::validate(&new_stage, &new_results), Ok(()));
+
+        let (again, _) = redistrict(&stage, &results, &options).unwrap();
+        for id in sorted::keys(&stage.units) {
+            assert_eq!(new_stage.units[&id].district, again.units[&id].district);
+        }
+    }
+}
@@ -0,0 +1,157 @@
+//! Transposition of results onto new district boundaries.
+use super::*;
+
+/// Whether a weight table maps old districts or units onto new districts.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Source { District, Unit }
+
+/// The share of the population of old district or unit `from` that lies in new district `to`.
+/// `from` is a `DistrictID` or a `UnitID`, not a poll number; see `source::transposition::from_csv`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Weight {
+    pub from: u32,
+    pub to: DistrictID,
+    pub share: f64,
+}
+
+/// Describes how old districts or units make up the new districts.
+#[derive(Debug, Clone)]
+pub struct Table {
+    pub source: Source,
+    pub weights: Vec<Weight>,
+    /// Names of the new districts. Unnamed districts are called `District <id>`.
+    pub names: HashMap<DistrictID, String>,
+}
+
+/// A part of a new district: its results in each election, its share and the old district it lies in.
+type Part<'a> = (Vec<&'a DistrictResults>, f64, DistrictID);
+
+/// Projects the results of `elections` onto the new districts in `table`.
+///
+/// Each new district gets the votes of its old districts or units multiplied by
+/// their shares. It lies in the area, and has the seats, of the old district
+/// contributing the largest share. Candidates are merged by party, and shared
+/// by all elections, see `assemble`. Returns the results in the order of `elections`.
+pub fn transpose(stage: &ElectionStage, elections: &[&ElectionResults], table: &Table) -> Result<(ElectionStage, Vec<ElectionResults>), Error> {
+    let what = match table.source { Source::District => "district", Source::Unit => "unit" };
+
+    let mut totals: HashMap<u32, f64> = HashMap::new();
+    for weight in table.weights.iter() {
+        if weight.share.is_nan() || weight.share < 0.0 {
+            return Err(Error::Inconsistent(format!("{} {} has a negative share in district {}", what, weight.from, weight.to)));
+        }
+        *totals.entry(weight.from).or_insert(0.0) += weight.share;
+    }
+    let mut over: Vec<u32> = totals.iter().filter(|(_, &total)| total > 1.0 + 1e-6).map(|(&from, _)| from).collect();
+    over.sort();
+    if let Some(from) = over.first() {
+        return Err(Error::Inconsistent(format!("shares of {} {} add up to {:.3}, more than 1", what, from, totals[from])));
+    }
+
+    let mut parts: HashMap<DistrictID, Vec<Part>> = HashMap::new();
+    for weight in table.weights.iter() {
+        let old_district = match table.source {
+            Source::District => Some(weight.from),
+            Source::Unit => stage.units.get(&weight.from).map(|u| u.district),
+        };
+        let old_district = old_district.filter(|d| stage.districts.contains_key(d)).ok_or(Error::Inconsistent(format!("{} {} is not in a known district", what, weight.from)))?;
+        let part = elections.iter().map(|results| match table.source {
+            Source::District => results.districts.get(&weight.from),
+            Source::Unit => results.units.get(&weight.from),
+        }.ok_or(Error::Inconsistent(format!("no results for {} {} on {}", what, weight.from, results.date)))).collect::<Result<_, _>>()?;
+        parts.entry(weight.to).or_default().push((part, weight.share, old_district));
+    }
+
+    let drafts = parts.into_iter().map(|(id, parts)| {
+        let main = parts.iter()
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap().then(b.2.cmp(&a.2)))
+            .map(|&(_, _, old)| &stage.districts[&old])
+            .unwrap();
+        Draft {
+            id,
+            district: District {
+                name: table.names.get(&id).cloned().unwrap_or(format!("District {}", id)),
+                seats: main.seats,
+                area: main.area,
+                candidates: HashSet::new(),
+            },
+            parts: (0..elections.len()).map(|i| parts.iter().map(|(part, share, _)| (part[i], *share)).collect()).collect(),
+        }
+    }).collect();
+
+    let dates: Vec<Date> = elections.iter().map(|results| results.date).collect();
+    let (stage, results, _) = assemble(stage, &dates, drafts);
+    Ok((stage, results))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn results(year: u32, votes: &[(CandidateID, u32)], district: impl Fn(CandidateID) -> DistrictID) -> ElectionResults {
+        let mut districts: HashMap<DistrictID, DistrictResults> = HashMap::new();
+        for &(candidate, votes) in votes {
+            districts.entry(district(candidate)).or_insert_with(|| DistrictResults {
+                party_votes: HashMap::new(),
+                party_list_source: PartyListSource::District,
+                candidate_votes: HashMap::new(),
+                electorate: None,
+                ballots: None,
+                invalid: None,
+                invalid_party: None,
+            }).candidate_votes.insert(candidate, votes);
+        }
+        ElectionResults { districts, date: Date::new(year, 1, 1), units: HashMap::new() }
+    }
+
+    #[test]
+    fn elections_share_candidates() {
+        // Candidate `10 * election + 2 * district + party` in districts 0 and 1.
+        // Party 2 only runs in the second election.
+        let mut builder = ElectionStage::builder();
+        builder.area(1, "Area", None);
+        for party in 0..3 {
+            builder.party(party, Party { name: format!("Party {}", party), color: 0, type_: PartyType::Other, abbreviation: None, names: HashMap::new() });
+        }
+        for district in 0..2 {
+            builder.district(district, format!("District {}", district), 1, 1);
+            for party in 0..2 {
+                builder.candidate(2 * district + party, None, Some(party as PartyID), district);
+                builder.candidate(10 + 2 * district + party, None, Some(party as PartyID), district);
+            }
+        }
+        builder.candidate(20, None, Some(2), 1);
+        let stage = builder.build().unwrap();
+
+        let district = |c: CandidateID| c % 10 / 2;
+        let first = results(2015, &[(0, 10), (1, 20), (2, 30), (3, 40)], district);
+        let second = results(2019, &[(10, 15), (11, 25), (12, 35), (13, 45), (20, 5)], |c| if c == 20 { 1 } else { district(c) });
+        let table = Table {
+            source: Source::District,
+            weights: vec![Weight { from: 0, to: 1, share: 1.0 }, Weight { from: 1, to: 1, share: 0.5 }],
+            names: HashMap::new(),
+        };
+
+        let (stage, results) = transpose(&stage, &[&first, &second], &table).unwrap();
+        assert_eq!(stage.districts[&1].candidates.len(), 3);
+        let party_votes = |results: &ElectionResults| {
+            let mut votes: Vec<(Option<PartyID>, u32)> = results.districts[&1].candidate_votes.iter()
+                .map(|(c, &v)| (stage.candidates[c].party, v))
+                .collect();
+            votes.sort();
+            votes
+        };
+        // Party 2 didn't run in the first election, so it has no votes there.
+        assert_eq!(party_votes(&results[0]), vec![(Some(0), 25), (Some(1), 40), (Some(2), 0)]);
+        assert_eq!(party_votes(&results[1]), vec![(Some(0), 33), (Some(1), 48), (Some(2), 3)]);
+        let ids = |results: &ElectionResults| {
+            let mut ids: Vec<CandidateID> = results.districts[&1].candidate_votes.keys().copied().collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(ids(&results[0]), ids(&results[1]));
+        for results in results.iter() {
+            assert!(validate::validate(&stage, results).is_ok());
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use crate::core::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io;
+
+#[derive(Deserialize)]
+struct EdgeRecord {
+    district: DistrictID,
+    neighbour: DistrictID,
+    #[serde(default)]
+    length: Option<f64>,
+}
+
+/// Reads an edge list with the columns `district,neighbour[,length]`.
+/// Each edge only needs to be listed once.
+pub fn from_csv(reader: impl io::Read) -> Result<Adjacency, csv::Error> {
+    let mut rdr = csv::ReaderBuilder::new().flexible(true).trim(csv::Trim::All).from_reader(reader);
+    let mut adjacency = Adjacency::new();
+    for record in rdr.deserialize::<EdgeRecord>() {
+        let record = record?;
+        adjacency.add(record.district, record.neighbour, record.length);
+    }
+    Ok(adjacency)
+}
+
+pub use crate::core::Ring;
+
+/// Derives adjacency from district boundaries, each made of one or more rings.
+///
+/// Districts are adjacent when their boundaries share at least one segment, so the
+/// boundaries must share vertices, as in a topologically consistent shapefile.
+/// Districts that only touch at a point are not adjacent.
+pub fn from_boundaries(boundaries: &Boundaries) -> Adjacency {
+    type Vertex = (i64, i64);
+
+    // Coordinates are rounded so that vertices written with slightly
+    // different precision still match.
+    let key = |(x, y): (f64, f64)| -> Vertex { ((x * 1e7).round() as i64, (y * 1e7).round() as i64) };
+
+    let mut segments: HashMap<(Vertex, Vertex), Vec<(DistrictID, f64)>> = HashMap::new();
+    for (&district, rings) in boundaries.iter() {
+        for ring in rings.iter() {
+            for (i, &a) in ring.iter().enumerate() {
+                let b = ring[(i + 1) % ring.len()];
+                let (ka, kb) = (key(a), key(b));
+                if ka == kb {
+                    continue
+                }
+                let length = ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt();
+                segments.entry(if ka < kb { (ka, kb) } else { (kb, ka) }).or_default().push((district, length));
+            }
+        }
+    }
+
+    let mut adjacency = Adjacency::new();
+    for owners in segments.values() {
+        for (i, &(a, length)) in owners.iter().enumerate() {
+            for &(b, _) in owners[i + 1..].iter() {
+                adjacency.add(a, b, Some(length));
+            }
+        }
+    }
+    adjacency
+}
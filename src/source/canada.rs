@@ -24,10 +24,25 @@ struct PollRecord {
     party: String,
 
     #[serde(rename = "Candidate Poll Votes Count/Votes du candidat pour le bureau")]
-    votes: u32
+    votes: u32,
+
+    #[serde(rename = "Polling Station Number/Numéro du bureau de scrutin", default)]
+    poll: String,
+
+    // Repeated on every candidate's row for the poll.
+    #[serde(rename = "Rejected Ballots for Polling Station/Bulletins rejetés du bureau", default, deserialize_with = "csv::invalid_option")]
+    rejected: Option<u32>,
+
+    #[serde(rename = "Electors for Polling Station/Électeurs du bureau", default, deserialize_with = "csv::invalid_option")]
+    electors: Option<u32>,
+}
+
+fn candidate_name(record: &PollRecord) -> String {
+    format!("{}{}{} {}", &record.candidate_name_first, if record.candidate_name_middle.is_empty() { "" } else { " " }, &record.candidate_name_middle, &record.candidate_name_last)
 }
 
-pub fn from_zip(reader: impl io::Read + io::Seek, date: Date) -> Result<(ElectionStage, ElectionResults, HashMap<u32, Grouping>), Box<dyn std::error::Error>> {
+/// Reads the poll-by-poll results of Elections Canada. Parties are looked up in `registry`.
+pub fn from_zip(reader: impl io::Read + io::Seek, date: Date, registry: &parties::Registry) -> Result<(ElectionStage, ElectionResults, HashMap<u32, Grouping>), Error> {
     let mut archive = zip::ZipArchive::new(reader)?; 
     let mut records: Vec<PollRecord> = Vec::new();
     for i in 0..archive.len() {
@@ -42,62 +57,51 @@ pub fn from_zip(reader: impl io::Read + io::Seek, date: Date) -> Result<(Electio
     let mut parties: HashMap<String, Party> = HashMap::new();
     for record in records.iter() {
         if let None = parties.get(&record.party) {
-            let data = match record.party.as_str() {
-                "Conservative" => Party {
-                    name: "Conservative".to_string(),
-                    color: 0x14294d,
-                    type_: PartyType::Conservative,
-                },
-                "Liberal" => Party {
-                    name: "Liberal".to_string(),
-                    color: 0xda121a,
-                    type_: PartyType::Liberal,
-                },
-                "NDP-New Democratic Party" => Party {
-                    name: "New Democrats".to_string(),
-                    color: 0xef7c00,
-                    type_: PartyType::SocialDemocratic,
-                },
-                "Bloc Québécois" => Party {
-                    name: "Bloc Québécois".to_string(),
-                    color: 0x42b7bf,
-                    type_: PartyType::Other,
-                },
-                "Green Party" => Party {
-                    name: "Green".to_string(),
-                    color: 0x3d9b35,
-                    type_: PartyType::Green,
-                },
-                "Independent" => continue,
-                _ => Party {
-                    name: record.party.to_string(),
-                    color: 0xaaaaaa,
-                    type_: PartyType::Other,
-                }
-            };
+            if record.party == "Independent" {
+                continue
+            }
+            let data = registry.party("Canada", &record.party);
             parties.insert(record.party.to_string(), data);
         }
     }
 
-    let parties_ids: HashMap<String, PartyID> = parties.keys().map(Clone::clone).enumerate().map(|(a, b)| (b, a as PartyID)).collect();
+    // IDs follow the order of party names, and candidates are numbered in order of
+    // district, name and party, so that the same files always give the same IDs.
+    let mut party_names: Vec<String> = parties.keys().map(Clone::clone).collect();
+    party_names.sort();
+    let parties_ids: HashMap<String, PartyID> = party_names.into_iter().enumerate().map(|(a, b)| (b, a as PartyID)).collect();
     let parties: HashMap<PartyID, Party> = parties.into_iter().map(|(s, party)| (parties_ids[&s], party)).collect();
 
-    let mut candidates: HashMap<String, Candidate> = HashMap::new();
-    let mut candidates_votes: HashMap<String, u32> = HashMap::new();
+    // Candidates with the same name in different districts are different people.
+    let candidate_key = |record: &PollRecord| (record.district_id, candidate_name(record), record.party.clone());
+    let mut candidates: HashMap<(DistrictID, String, String), Candidate> = HashMap::new();
+    let mut candidates_votes: HashMap<(DistrictID, String, String), u32> = HashMap::new();
     for record in records.iter() {
-        let name = format!("{}{}{} {}", &record.candidate_name_first, if record.candidate_name_middle.is_empty() { "" } else { " " }, &record.candidate_name_middle, &record.candidate_name_last);
-
-        if let None = candidates.get(&name) {
-            candidates.insert(name.clone(), Candidate {
-                name: Some(name.clone()),
+        let key = candidate_key(record);
+        if !candidates.contains_key(&key) {
+            candidates.insert(key.clone(), Candidate {
+                name: Some(key.1.clone()),
                 party: parties_ids.get(&record.party).map(|r| *r),
+                election: None,
             });
         }
 
-        *candidates_votes.entry(name).or_insert(0) += record.votes;
+        *candidates_votes.entry(key).or_insert(0) += record.votes;
     }
-    let candidates_ids: HashMap<String, CandidateID> = candidates.keys().map(Clone::clone).enumerate().map(|(a, b)| (b, a as CandidateID)).collect();
-    let candidates: HashMap<CandidateID, Candidate> = candidates.into_iter().map(|(name, candidate)| (candidates_ids[&name], candidate)).collect();
+
+    // Tell apart candidates of the same name in one district by their party.
+    let mut keys: Vec<(DistrictID, String, String)> = candidates.keys().map(Clone::clone).collect();
+    keys.sort();
+    for pair in keys.windows(2) {
+        if pair[0].0 == pair[1].0 && pair[0].1 == pair[1].1 {
+            for key in pair.iter() {
+                candidates.get_mut(key).unwrap().name = Some(format!("{} ({})", key.1, key.2));
+            }
+        }
+    }
+    let candidates_ids: HashMap<(DistrictID, String, String), CandidateID> = keys.into_iter().enumerate().map(|(a, b)| (b, a as CandidateID)).collect();
+    let candidates_votes: HashMap<CandidateID, u32> = candidates_votes.into_iter().map(|(key, votes)| (candidates_ids[&key], votes)).collect();
+    let candidates: HashMap<CandidateID, Candidate> = candidates.into_iter().map(|(key, candidate)| (candidates_ids[&key], candidate)).collect();
 
     let mut districts: HashMap<DistrictID, District> = HashMap::new();
     for record in records.iter() {
@@ -110,52 +114,110 @@ pub fn from_zip(reader: impl io::Read + io::Seek, date: Date) -> Result<(Electio
             });
         }
 
-        let name = format!("{}{}{} {}", &record.candidate_name_first, if record.candidate_name_middle.is_empty() { "" } else { " " }, &record.candidate_name_middle, &record.candidate_name_last);
-        let candidate_idx = *candidates_ids.get(&name).unwrap();
+        let candidate_idx = candidates_ids[&candidate_key(record)];
         let district: &mut District = districts.get_mut(&record.district_id).unwrap();
         if !district.candidates.contains(&candidate_idx) {
             district.candidates.insert(candidate_idx);
         }
     }
 
+    // Rejected ballots and electors of each poll.
+    type Counts = (Option<u32>, Option<u32>);
+    let mut polls: HashMap<(DistrictID, &str), Counts> = HashMap::new();
+    for record in records.iter() {
+        polls.insert((record.district_id, record.poll.as_str()), (record.rejected, record.electors));
+    }
+
+    // Each poll becomes a unit, numbered in order of district and poll number.
+    let mut poll_keys: Vec<(DistrictID, &str)> = polls.keys().copied().collect();
+    poll_keys.sort();
+    let units_ids: HashMap<(DistrictID, &str), UnitID> = poll_keys.iter().enumerate().map(|(i, &key)| (key, i as UnitID)).collect();
+    let units: HashMap<UnitID, Unit> = poll_keys.iter().map(|&(district, poll)| (units_ids[&(district, poll)], Unit {
+        name: poll.to_string(),
+        district,
+    })).collect();
+
+    let mut units_results: HashMap<UnitID, DistrictResults> = HashMap::with_capacity(units.len());
+    for record in records.iter() {
+        let key = (record.district_id, record.poll.as_str());
+        let results = units_results.entry(units_ids[&key]).or_insert_with(|| DistrictResults {
+            candidate_votes: HashMap::new(),
+            party_list_source: PartyListSource::District,
+            party_votes: HashMap::new(),
+            electorate: polls[&key].1,
+            ballots: polls[&key].0,
+            invalid: polls[&key].0,
+            invalid_party: None,
+        });
+
+        *results.candidate_votes.entry(candidates_ids[&candidate_key(record)]).or_insert(0) += record.votes;
+        results.ballots = results.ballots.map(|b| b + record.votes);
+    }
+
+    let mut rejected: HashMap<DistrictID, u32> = HashMap::new();
+    let mut electors: HashMap<DistrictID, u32> = HashMap::new();
+    for (&(district, _), &(poll_rejected, poll_electors)) in polls.iter() {
+        if let Some(n) = poll_rejected {
+            *rejected.entry(district).or_insert(0) += n;
+        }
+        if let Some(n) = poll_electors {
+            *electors.entry(district).or_insert(0) += n;
+        }
+    }
+
     let mut districts_results: HashMap<DistrictID, DistrictResults> = HashMap::with_capacity(districts.len());
     for (&id, district) in districts.iter() {
         let mut votes: HashMap<CandidateID, u32> = HashMap::new();
         for candidate in district.candidates.iter() {
-            votes.insert(*candidate, candidates_votes[candidates[candidate].name.as_ref().unwrap().as_str()]);
+            votes.insert(*candidate, candidates_votes[candidate]);
         }
-        districts_results.insert(id, DistrictResults { candidate_votes: votes, party_list_source: PartyListSource::District, party_votes: HashMap::new() });
+        let invalid = rejected.get(&id).copied();
+        let ballots = invalid.map(|invalid| invalid + votes.values().sum::<u32>());
+        districts_results.insert(id, DistrictResults {
+            candidate_votes: votes,
+            party_list_source: PartyListSource::District,
+            party_votes: HashMap::new(),
+            electorate: electors.get(&id).copied(),
+            ballots,
+            invalid,
+            invalid_party: None,
+        });
     }
     
+    // Populations from the 2011 census, which the 2013 representation order is based on.
     let mut areas: HashMap<AreaID, Area> = [
-            (10, "Newfoundland and Labrador"),
-            (11, "Prince Edward Island"),
-            (12, "Nova Scotia"), 
-            (13, "New Brunswick"),
-            (24, "Quebec"),
-            (35, "Ontario"),
-            (46, "Manitoba"),
-            (47, "Saskatchewan"),
-            (48, "Alberta"),
-            (59, "British Columbia"),
-            (60, "Yukon"),
-            (61, "Northwest Territories"),
-            (62, "Nunavut")].iter().map(|(id, name)| 
-                (*id, Area {
+            (10, "Newfoundland and Labrador", 514536),
+            (11, "Prince Edward Island", 140204),
+            (12, "Nova Scotia", 921727),
+            (13, "New Brunswick", 751171),
+            (24, "Quebec", 7903001),
+            (35, "Ontario", 12851821),
+            (46, "Manitoba", 1208268),
+            (47, "Saskatchewan", 1033381),
+            (48, "Alberta", 3645257),
+            (59, "British Columbia", 4400057),
+            (60, "Yukon", 33897),
+            (61, "Northwest Territories", 41462),
+            (62, "Nunavut", 31906)].iter().map(|&(id, name, population)|
+                (id, Area {
                     name: name.to_string(),
                     districts: HashSet::new(),
                     candidates: HashSet::with_capacity(0),
                     seats: 0,
+                    population: Some(population),
+                    parent: None,
                 })).collect();
 
     for (&district_id, district) in districts.iter() {
-        let area = areas.get_mut(&district.area).unwrap();
+        let area = areas.get_mut(&district.area).ok_or(Error::Missing(Entity::Area, district.area as u32))?;
         area.districts.insert(district_id);
     }
 
     let mut groupings: HashMap<u32, Grouping> = HashMap::new();
-    groupings.insert(1u32, Grouping(districts.keys().map(|&i| {let mut h = HashSet::with_capacity(1); h.insert(i); h }).collect()));
-    // TODO: groupings for 2
+    let mut district_ids: Vec<DistrictID> = districts.keys().copied().collect();
+    district_ids.sort();
+    groupings.insert(1u32, Grouping(district_ids.iter().map(|&i| {let mut h = HashSet::with_capacity(1); h.insert(i); h }).collect()));
+    // Pairs and larger groups are generated from the adjacency graph by `extract`.
 
     return Ok((
         ElectionStage {
@@ -163,10 +225,14 @@ pub fn from_zip(reader: impl io::Read + io::Seek, date: Date) -> Result<(Electio
             areas,
             candidates,
             parties,
+            adjacency: None,
+            units,
+            unit_adjacency: None,
         },
         ElectionResults {
             districts: districts_results,
-            date
+            date,
+            units: units_results,
         },
         groupings,
     ))
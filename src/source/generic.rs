@@ -0,0 +1,635 @@
+//! Reads a tidy CSV file of any country, one row per candidate or party in a
+//! district or polling unit, described by a `Mapping` instead of a module of
+//! its own. A mapping as read by `extract` from `dataset/<name>/mapping.toml`:
+//!
+//! ```toml
+//! file = "src.csv"
+//! country = "Canada"
+//! election = "2019 federal election"
+//! date = "2019-10-21"
+//! delimiter = ","
+//! encoding = "utf-8"
+//! no_party = ["Independent"]
+//!
+//! [columns]
+//! district_id = "District Number"
+//! district_name = "District Name"
+//! area = "Province"
+//! unit = "Poll"
+//! candidate = ["First Name", "Middle Name", "Family Name"]
+//! party = "Party"
+//! votes = "Votes"
+//! electorate = "Electors for Poll"
+//!
+//! [aggregate]
+//! electorate = "repeated"
+//!
+//! [parties."People's Party - PPC"]
+//! name = "People's Party"
+//! abbreviation = "PPC"
+//! color = "4e2e8a"
+//! ideology = "conservative"
+//! ```
+use crate::core::*;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::{io, fmt};
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Mapping {
+    /// The CSV file, relative to the mapping. Only used by `extract`, which defaults to `src.csv`.
+    #[serde(default)]
+    pub file: Option<String>,
+    /// E.g. `Canada`, for the dataset's metadata.
+    #[serde(default)]
+    pub country: Option<String>,
+    /// E.g. `2019 federal election`, for the dataset's metadata.
+    #[serde(default)]
+    pub election: Option<String>,
+    /// `YYYY-MM-DD`.
+    #[serde(default)]
+    pub date: Option<String>,
+    #[serde(default = "comma")]
+    pub delimiter: char,
+    #[serde(default)]
+    pub encoding: Encoding,
+    /// Lines before the header, e.g. a title.
+    #[serde(default)]
+    pub skip_lines: usize,
+    /// Separator of thousands in numbers, e.g. `.` for `12.345`.
+    #[serde(default)]
+    pub thousands: Option<char>,
+    pub columns: Columns,
+    #[serde(default)]
+    pub aggregate: Aggregation,
+    /// Parties by their name in the file, added to or overriding those of
+    /// `country` in the registry, see `parties::Registry::merge`.
+    #[serde(default)]
+    pub parties: HashMap<String, parties::PartyInfo>,
+    /// Values of the party column that mean no party, e.g. `Independent`. An empty value always does.
+    #[serde(default)]
+    pub no_party: Vec<String>,
+}
+
+fn comma() -> char {
+    ','
+}
+
+/// Names of the columns to read. Either `district_id` or `district_name`, and
+/// either `votes` or `party_votes` must be given.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Columns {
+    /// Numeric district IDs. Without it, districts are numbered from 1 in order of name.
+    #[serde(default)]
+    pub district_id: Option<String>,
+    #[serde(default)]
+    pub district_name: Option<String>,
+    /// The name of the district's area. Areas are numbered from 1 in order of
+    /// name. Without it, all districts are in one area.
+    #[serde(default)]
+    pub area: Option<String>,
+    /// Polling units within a district, whose results are kept as well.
+    #[serde(default)]
+    pub unit: Option<String>,
+    /// One column, or several that are joined with spaces, skipping empty ones.
+    /// Without it, candidates have no names.
+    #[serde(default)]
+    pub candidate: Option<Names>,
+    #[serde(default)]
+    pub party: Option<String>,
+    /// Votes for the candidate.
+    #[serde(default)]
+    pub votes: Option<String>,
+    /// Votes for the party, e.g. the second vote of mixed systems.
+    #[serde(default)]
+    pub party_votes: Option<String>,
+    /// Seats of the district, 1 if not given.
+    #[serde(default)]
+    pub seats: Option<String>,
+    #[serde(default)]
+    pub electorate: Option<String>,
+    #[serde(default)]
+    pub invalid: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Names {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Names {
+    fn columns(&self) -> &[String] {
+        match self {
+            Names::One(name) => std::slice::from_ref(name),
+            Names::Many(names) => names,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Encoding {
+    /// A byte order mark is skipped.
+    #[default]
+    #[serde(rename = "utf-8", alias = "utf8")]
+    Utf8,
+    #[serde(alias = "iso-8859-1")]
+    Latin1,
+    #[serde(rename = "windows-1252", alias = "cp1252")]
+    Windows1252,
+}
+
+/// Characters of bytes 0x80-0x9f in Windows-1252, which are control characters in Latin-1.
+const WINDOWS_1252: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+];
+
+impl Encoding {
+    fn decode(self, bytes: Vec<u8>) -> Result<String, Error> {
+        match self {
+            Encoding::Utf8 => {
+                let text = String::from_utf8(bytes).map_err(|e| Error::Source(Box::new(e)))?;
+                Ok(match text.strip_prefix('\u{feff}') {
+                    Some(rest) => rest.to_owned(),
+                    None => text,
+                })
+            },
+            Encoding::Latin1 => Ok(bytes.into_iter().map(char::from).collect()),
+            Encoding::Windows1252 => Ok(bytes.into_iter().map(|b| match b {
+                0x80..=0x9f => WINDOWS_1252[(b - 0x80) as usize],
+                _ => char::from(b),
+            }).collect()),
+        }
+    }
+}
+
+/// How values that each row gives for its whole unit, or district without
+/// units, are combined. Votes are always added up.
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Aggregate {
+    /// Each row repeats the total, e.g. the electorate on every candidate's row.
+    /// Rows that disagree are an error.
+    Repeated,
+    /// Each row has a part of the total.
+    Sum,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Aggregation {
+    #[serde(default = "repeated")]
+    pub electorate: Aggregate,
+    #[serde(default = "repeated")]
+    pub invalid: Aggregate,
+}
+
+fn repeated() -> Aggregate {
+    Aggregate::Repeated
+}
+
+impl Default for Aggregation {
+    fn default() -> Self {
+        Aggregation { electorate: Aggregate::Repeated, invalid: Aggregate::Repeated }
+    }
+}
+
+fn error(line: u64, msg: impl fmt::Display) -> Error {
+    Error::Source(format!("line {}: {}", line, msg).into())
+}
+
+fn mapping_error(msg: impl fmt::Display) -> Error {
+    Error::Source(format!("mapping: {}", msg).into())
+}
+
+/// Positions of the mapped columns in the header.
+struct Positions {
+    district_id: Option<usize>,
+    district_name: Option<usize>,
+    area: Option<usize>,
+    unit: Option<usize>,
+    candidate: Vec<usize>,
+    party: Option<usize>,
+    votes: Option<usize>,
+    party_votes: Option<usize>,
+    seats: Option<usize>,
+    electorate: Option<usize>,
+    invalid: Option<usize>,
+}
+
+impl Positions {
+    fn new(columns: &Columns, header: &csv::StringRecord) -> Result<Positions, Error> {
+        if columns.district_id.is_none() && columns.district_name.is_none() {
+            return Err(mapping_error("needs a district_id or district_name column"));
+        }
+        if columns.votes.is_none() && columns.party_votes.is_none() {
+            return Err(mapping_error("needs a votes or party_votes column"));
+        }
+        let find = |name: &String| header.iter().position(|h| h == name)
+            .ok_or_else(|| mapping_error(format!("no column {:?} in the file", name)));
+        let find_optional = |name: &Option<String>| name.as_ref().map(find).transpose();
+        Ok(Positions {
+            district_id: find_optional(&columns.district_id)?,
+            district_name: find_optional(&columns.district_name)?,
+            area: find_optional(&columns.area)?,
+            unit: find_optional(&columns.unit)?,
+            candidate: columns.candidate.as_ref().map(|c| c.columns().iter().map(find).collect()).transpose()?.unwrap_or_default(),
+            party: find_optional(&columns.party)?,
+            votes: find_optional(&columns.votes)?,
+            party_votes: find_optional(&columns.party_votes)?,
+            seats: find_optional(&columns.seats)?,
+            electorate: find_optional(&columns.electorate)?,
+            invalid: find_optional(&columns.invalid)?,
+        })
+    }
+}
+
+/// One row of the file.
+struct Record {
+    line: u64,
+    district_id: Option<DistrictID>,
+    district_name: String,
+    area: String,
+    unit: Option<String>,
+    candidate: Option<String>,
+    party: Option<String>,
+    votes: Option<u32>,
+    party_votes: Option<u32>,
+    seats: Option<SeatCount>,
+    electorate: Option<u32>,
+    invalid: Option<u32>,
+}
+
+/// Combines `value` into `total` by `rule`.
+fn aggregate(total: &mut Option<u32>, value: Option<u32>, rule: Aggregate, line: u64, what: &str) -> Result<(), Error> {
+    match (total.as_mut(), value, rule) {
+        (_, None, _) => {},
+        (None, Some(value), _) => *total = Some(value),
+        (Some(total), Some(value), Aggregate::Sum) => *total += value,
+        (Some(total), Some(value), Aggregate::Repeated) => if *total != value {
+            return Err(Error::Inconsistent(format!("line {}: {} {} differs from {} on an earlier row", line, what, value, total)));
+        },
+    }
+    Ok(())
+}
+
+/// Reads `reader` as described by `mapping`. The election date is taken from
+/// the mapping unless `date` is given. Parties are looked up in `registry`
+/// under the mapping's country.
+pub fn from_csv(mut reader: impl io::Read, mapping: &Mapping, date: Option<Date>, registry: &parties::Registry) -> Result<(ElectionStage, ElectionResults, Groupings), Error> {
+    let date = match (date, &mapping.date) {
+        (Some(date), _) => date,
+        (None, Some(date)) => date.parse().map_err(|e| mapping_error(format!("date: {}", e)))?,
+        (None, None) => return Err(mapping_error("no date, it must be given")),
+    };
+    if !mapping.delimiter.is_ascii() {
+        return Err(mapping_error(format!("delimiter {:?} isn't an ASCII character", mapping.delimiter)));
+    }
+
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes).map_err(|e| Error::Source(Box::new(e)))?;
+    let contents = mapping.encoding.decode(bytes)?;
+    let contents = contents.splitn(mapping.skip_lines + 1, '\n').last().unwrap_or("");
+    let mut rdr = csv::ReaderBuilder::new().delimiter(mapping.delimiter as u8).trim(csv::Trim::All).from_reader(contents.as_bytes());
+    let positions = Positions::new(&mapping.columns, rdr.headers()?)?;
+
+    let no_party: HashSet<&str> = mapping.no_party.iter().map(String::as_str).collect();
+    let mut records = Vec::new();
+    for row in rdr.records() {
+        let row = row?;
+        let line = row.position().map(|p| p.line()).unwrap_or(0) + mapping.skip_lines as u64;
+        let text = |i: Option<usize>| i.and_then(|i| row.get(i)).filter(|s| !s.is_empty());
+        let number = |i: Option<usize>, what: &str| -> Result<Option<u32>, Error> {
+            match text(i) {
+                Some(s) => {
+                    let digits: String = s.chars().filter(|&c| Some(c) != mapping.thousands).collect();
+                    digits.parse().map(Some).map_err(|e| error(line, format!("{} {:?}: {}", what, s, e)))
+                },
+                None => Ok(None),
+            }
+        };
+
+        let district_id = number(positions.district_id, "district")?;
+        if positions.district_id.is_some() && district_id.is_none() {
+            return Err(error(line, "no district"));
+        }
+        let district_name = text(positions.district_name).map(str::to_owned)
+            .or_else(|| district_id.map(|id| id.to_string()))
+            .ok_or_else(|| error(line, "no district"))?;
+        let candidate: Vec<&str> = positions.candidate.iter().filter_map(|&i| text(Some(i))).collect();
+        let seats = number(positions.seats, "seats")?
+            .map(|seats| SeatCount::try_from(seats).map_err(|e| error(line, format!("seats {}: {}", seats, e))))
+            .transpose()?;
+        records.push(Record {
+            line,
+            district_id,
+            district_name,
+            area: text(positions.area).or(mapping.country.as_deref()).unwrap_or("All").to_owned(),
+            unit: text(positions.unit).map(str::to_owned),
+            candidate: if candidate.is_empty() { None } else { Some(candidate.join(" ")) },
+            party: text(positions.party).filter(|p| !no_party.contains(p)).map(str::to_owned),
+            votes: number(positions.votes, "votes")?,
+            party_votes: number(positions.party_votes, "party votes")?,
+            seats,
+            electorate: number(positions.electorate, "electorate")?,
+            invalid: number(positions.invalid, "invalid votes")?,
+        });
+    }
+    if positions.unit.is_some() {
+        if let Some(record) = records.iter().find(|r| r.unit.is_none()) {
+            return Err(error(record.line, "no unit"));
+        }
+    }
+
+    // Without IDs, districts are numbered in order of name.
+    let district_ids: HashMap<String, DistrictID> = if positions.district_id.is_some() {
+        records.iter().map(|r| (r.district_name.clone(), r.district_id.unwrap())).collect()
+    } else {
+        let mut names: Vec<&String> = records.iter().map(|r| &r.district_name).collect::<HashSet<_>>().into_iter().collect();
+        names.sort();
+        names.into_iter().enumerate().map(|(i, name)| (name.clone(), i as DistrictID + 1)).collect()
+    };
+    let district_id = |r: &Record| r.district_id.unwrap_or_else(|| district_ids[&r.district_name]);
+
+    let mut area_names: Vec<&String> = records.iter().map(|r| &r.area).collect::<HashSet<_>>().into_iter().collect();
+    area_names.sort();
+    let mut areas: HashMap<AreaID, Area> = HashMap::with_capacity(area_names.len());
+    let mut area_ids: HashMap<&str, AreaID> = HashMap::with_capacity(area_names.len());
+    for (i, name) in area_names.into_iter().enumerate() {
+        let id = AreaID::try_from(i + 1).map_err(|_| Error::Inconsistent("more areas than area IDs".to_owned()))?;
+        area_ids.insert(name, id);
+        areas.insert(id, Area {
+            name: name.clone(),
+            districts: HashSet::new(),
+            candidates: HashSet::with_capacity(0),
+            seats: 0,
+            population: None,
+            parent: None,
+        });
+    }
+
+    let country = mapping.country.as_deref().unwrap_or_default();
+    let mut registry = registry.clone();
+    registry.merge_country(country, mapping.parties.clone());
+    let mut party_names: Vec<&String> = records.iter().filter_map(|r| r.party.as_ref()).collect::<HashSet<_>>().into_iter().collect();
+    party_names.sort();
+    let mut parties: HashMap<PartyID, Party> = HashMap::with_capacity(party_names.len());
+    let mut party_ids: HashMap<&str, PartyID> = HashMap::with_capacity(party_names.len());
+    for (i, name) in party_names.into_iter().enumerate() {
+        let id = PartyID::try_from(i).map_err(|_| Error::Inconsistent("more parties than party IDs".to_owned()))?;
+        party_ids.insert(name, id);
+        parties.insert(id, registry.party(country, name));
+    }
+
+    let mut districts: HashMap<DistrictID, District> = HashMap::new();
+    for record in records.iter() {
+        let id = district_id(record);
+        let area = area_ids[record.area.as_str()];
+        let district = districts.entry(id).or_insert_with(|| District {
+            name: record.district_name.clone(),
+            area,
+            candidates: HashSet::new(),
+            seats: record.seats.unwrap_or(1),
+        });
+        if district.area != area {
+            return Err(Error::Inconsistent(format!("line {}: district {} is in area {}, but in {} on an earlier row", record.line, id, record.area, areas[&district.area].name)));
+        }
+        if let Some(seats) = record.seats.filter(|&s| s != district.seats) {
+            return Err(Error::Inconsistent(format!("line {}: district {} has {} seats, but {} on an earlier row", record.line, id, seats, district.seats)));
+        }
+        areas.get_mut(&area).unwrap().districts.insert(id);
+    }
+
+    // Candidates are numbered in order of district, name and party, and those
+    // with the same name in one district are told apart by their party.
+    type CandidateKey = (DistrictID, Option<String>, Option<String>);
+    let candidate_key = |r: &Record| (district_id(r), r.candidate.clone(), r.party.clone());
+    let mut keys: Vec<CandidateKey> = records.iter().filter(|r| r.votes.is_some()).map(candidate_key).collect::<HashSet<_>>().into_iter().collect();
+    keys.sort();
+    let mut candidates: HashMap<CandidateID, Candidate> = HashMap::with_capacity(keys.len());
+    for (i, key) in keys.iter().enumerate() {
+        let same_name = |other: &CandidateKey| other != key && other.0 == key.0 && other.1 == key.1;
+        let name = match (&key.1, &key.2) {
+            (Some(name), Some(party)) if keys.iter().any(same_name) => Some(format!("{} ({})", name, party)),
+            (name, _) => name.clone(),
+        };
+        candidates.insert(i as CandidateID, Candidate { name, party: key.2.as_ref().map(|p| party_ids[p.as_str()]), election: None });
+        districts.get_mut(&key.0).unwrap().candidates.insert(i as CandidateID);
+    }
+    let candidate_ids: HashMap<CandidateKey, CandidateID> = keys.into_iter().enumerate().map(|(i, key)| (key, i as CandidateID)).collect();
+
+    // Each polling unit is numbered in order of district and name.
+    let mut unit_keys: Vec<(DistrictID, &str)> = records.iter()
+        .filter_map(|r| r.unit.as_ref().map(|unit| (district_id(r), unit.as_str())))
+        .collect::<HashSet<_>>().into_iter().collect();
+    unit_keys.sort();
+    let unit_ids: HashMap<(DistrictID, &str), UnitID> = unit_keys.iter().enumerate().map(|(i, &key)| (key, i as UnitID)).collect();
+    let units: HashMap<UnitID, Unit> = unit_keys.iter().map(|&(district, name)| (unit_ids[&(district, name)], Unit {
+        name: name.to_owned(),
+        district,
+    })).collect();
+
+    // Results of each unit, or of each district without units.
+    let mut place_results: HashMap<u32, DistrictResults> = HashMap::new();
+    for record in records.iter() {
+        let place = match &record.unit {
+            Some(unit) => unit_ids[&(district_id(record), unit.as_str())],
+            None => district_id(record),
+        };
+        let results = place_results.entry(place).or_insert_with(|| DistrictResults {
+            party_votes: HashMap::new(),
+            party_list_source: PartyListSource::District,
+            candidate_votes: HashMap::new(),
+            electorate: None,
+            ballots: None,
+            invalid: None,
+            invalid_party: None,
+        });
+        if let Some(votes) = record.votes {
+            *results.candidate_votes.entry(candidate_ids[&candidate_key(record)]).or_insert(0) += votes;
+        }
+        if let (Some(votes), Some(party)) = (record.party_votes, &record.party) {
+            *results.party_votes.entry(party_ids[party.as_str()]).or_insert(0) += votes;
+        }
+        aggregate(&mut results.electorate, record.electorate, mapping.aggregate.electorate, record.line, "electorate")?;
+        aggregate(&mut results.invalid, record.invalid, mapping.aggregate.invalid, record.line, "invalid votes")?;
+    }
+    for results in place_results.values_mut() {
+        let valid: u32 = if results.candidate_votes.is_empty() { results.party_votes.values().sum() } else { results.candidate_votes.values().sum() };
+        results.ballots = results.invalid.map(|invalid| invalid + valid);
+    }
+
+    let (units_results, mut districts_results) = if positions.unit.is_some() {
+        let mut results = ElectionResults { districts: HashMap::new(), date, units: place_results };
+        for &id in districts.keys() {
+            let units: Vec<UnitID> = unit_keys.iter().filter(|&&(d, _)| d == id).map(|key| unit_ids[key]).collect();
+            let total = results.sum_units(units.iter()).ok_or_else(|| Error::Inconsistent(format!("district {} has no units", id)))?;
+            results.districts.insert(id, total);
+        }
+        (results.units, results.districts)
+    } else {
+        (HashMap::new(), place_results)
+    };
+    // Candidates who stood in some units only still get an entry for the district.
+    for (id, results) in districts_results.iter_mut().filter(|(_, r)| !r.candidate_votes.is_empty()) {
+        for &candidate in districts[id].candidates.iter() {
+            results.candidate_votes.entry(candidate).or_insert(0);
+        }
+    }
+
+    let mut sorted_ids: Vec<DistrictID> = districts.keys().copied().collect();
+    sorted_ids.sort();
+    let mut groupings = Groupings::new();
+    groupings.insert(1u32, Grouping(sorted_ids.iter().map(|&i| { let mut h = HashSet::with_capacity(1); h.insert(i); h }).collect()));
+
+    Ok((
+        ElectionStage {
+            districts,
+            areas,
+            candidates,
+            parties,
+            adjacency: None,
+            units,
+            unit_adjacency: None,
+        },
+        ElectionResults {
+            districts: districts_results,
+            date,
+            units: units_results,
+        },
+        groupings,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping(toml: &str) -> Mapping {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn read(csv: &[u8], mapping: &Mapping) -> Result<(ElectionStage, ElectionResults, Groupings), Error> {
+        from_csv(csv, mapping, None, &parties::Registry::builtin())
+    }
+
+    /// The candidate of `party`, by name, in `district`.
+    fn candidate(stage: &ElectionStage, district: DistrictID, party: &str) -> CandidateID {
+        *stage.districts[&district].candidates.iter()
+            .find(|c| stage.candidates[c].party.map(|p| stage.parties[&p].name.as_str()) == Some(party))
+            .unwrap()
+    }
+
+    const POLLS: &str = "\
+district,poll,name,party,votes,electors
+Avalon,1,Ann Lee,Liberal,120,400
+Avalon,1,Bo Chan,Conservative,80,400
+Avalon,2,Ann Lee,Liberal,50,150
+Avalon,2,Bo Chan,Conservative,60,150
+Bonavista,1,Cy Roy,Liberal,30,90
+Bonavista,1,Di Fox,Independent,40,90
+";
+
+    const POLLS_MAPPING: &str = r#"
+        country = "Canada"
+        date = "2019-10-21"
+        no_party = ["Independent"]
+
+        [columns]
+        district_name = "district"
+        unit = "poll"
+        candidate = "name"
+        party = "party"
+        votes = "votes"
+        electorate = "electors"
+    "#;
+
+    #[test]
+    fn repeated_totals_are_counted_once() {
+        let (stage, results, _) = read(POLLS.as_bytes(), &mapping(POLLS_MAPPING)).unwrap();
+
+        // Districts are numbered by name, parties are found in the registry.
+        assert_eq!(stage.districts[&1].name, "Avalon");
+        assert_eq!(stage.units.len(), 3);
+        let liberal = candidate(&stage, 1, "Liberal");
+        assert_eq!(stage.candidates[&liberal].name.as_deref(), Some("Ann Lee"));
+        assert_eq!(stage.parties[&stage.candidates[&liberal].party.unwrap()].color, 0xda121a);
+
+        let avalon = &results.districts[&1];
+        assert_eq!(avalon.electorate, Some(550));
+        assert_eq!(avalon.candidate_votes[&liberal], 170);
+        assert_eq!(avalon.candidate_votes[&candidate(&stage, 1, "Conservative")], 140);
+        let independent = *stage.districts[&2].candidates.iter().find(|c| stage.candidates[c].party.is_none()).unwrap();
+        assert_eq!(results.districts[&2].candidate_votes[&independent], 40);
+        assert_eq!(results.districts[&2].electorate, Some(90));
+    }
+
+    #[test]
+    fn repeated_totals_must_agree() {
+        let csv = POLLS.replace("Avalon,1,Bo Chan,Conservative,80,400", "Avalon,1,Bo Chan,Conservative,80,401");
+        match read(csv.as_bytes(), &mapping(POLLS_MAPPING)) {
+            Err(Error::Inconsistent(msg)) => assert!(msg.starts_with("line 3: electorate 401")),
+            other => panic!("expected an inconsistency, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn summed_totals_are_added_up() {
+        let csv = "\
+id,district,party,votes,invalid
+7,Nord,Liberal,100,3
+7,Nord,New Democrats,50,2
+8,Sud,Liberal,20,1
+";
+        let mapping = mapping(r#"
+            country = "Canada"
+            date = "2019-10-21"
+
+            [columns]
+            district_id = "id"
+            district_name = "district"
+            party = "party"
+            votes = "votes"
+            invalid = "invalid"
+
+            [aggregate]
+            invalid = "sum"
+        "#);
+        let (stage, results, groupings) = read(csv.as_bytes(), &mapping).unwrap();
+        assert_eq!(stage.districts[&7].name, "Nord");
+        assert_eq!(results.districts[&7].invalid, Some(5));
+        assert_eq!(results.districts[&7].ballots, Some(155));
+        assert_eq!(results.districts[&8].invalid, Some(1));
+        assert_eq!(groupings[&1].0.len(), 2);
+    }
+
+    #[test]
+    fn windows_1252_is_decoded() {
+        let mut csv = b"Wahlergebnis\n".to_vec();
+        csv.extend_from_slice(b"Wahlkreis;Partei;Stimmen\n");
+        // "Zürich", "Pa’s Café €" and 1.234 votes, with bytes 0x80-0x9f that differ from Latin-1.
+        csv.extend_from_slice(b"Z\xfcrich;Pa\x92s Caf\xe9 \x80;1.234\n");
+        let mapping = mapping(r#"
+            date = "2021-09-26"
+            delimiter = ";"
+            encoding = "cp1252"
+            skip_lines = 1
+            thousands = "."
+
+            [columns]
+            district_name = "Wahlkreis"
+            party = "Partei"
+            votes = "Stimmen"
+        "#);
+        let (stage, results, _) = read(&csv, &mapping).unwrap();
+        assert_eq!(stage.districts[&1].name, "Zürich");
+        let candidate = candidate(&stage, 1, "Pa’s Café €");
+        assert_eq!(results.districts[&1].candidate_votes[&candidate], 1234);
+    }
+}
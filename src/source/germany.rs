@@ -1,54 +1,66 @@
 use crate::core::*;
+use crate::apportionment;
 use std::collections::{HashMap, HashSet};
-use std::{io,fmt};
-use std::borrow::Cow;
+use std::io;
 
-pub enum Error {
-    Custom(Cow<'static, str>),
-    Parse(std::num::ParseIntError, Cow<'static, str>),
-    Csv(csv::Error)
-}
+/// Dates of Bundestag elections, used to date the previous election's results.
+const ELECTION_DATES: &[(u32, u8, u8)] = &[
+    (1949, 8, 14), (1953, 9, 6), (1957, 9, 15), (1961, 9, 17), (1965, 9, 19), (1969, 9, 28),
+    (1972, 11, 19), (1976, 10, 3), (1980, 10, 5), (1983, 3, 6), (1987, 1, 25), (1990, 12, 2),
+    (1994, 10, 16), (1998, 9, 27), (2002, 9, 22), (2005, 9, 18), (2009, 9, 27), (2013, 9, 22),
+    (2017, 9, 24), (2021, 9, 26), (2025, 2, 23),
+];
 
-impl fmt::Debug for Error {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        fmt::Display::fmt(self, f)
-    }
+/// Date of the last Bundestag election before `date`.
+fn previous_election(date: Date) -> Option<Date> {
+    ELECTION_DATES.iter().rev()
+        .map(|&(year, month, day)| Date::new(year, month, day))
+        .find(|&d| d < date)
 }
 
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Error::Csv(csv) => {
-                write!(f, "germany parsing error: csv error: {}", csv)
-            },
-            Error::Custom(msg) => {
-                write!(f, "germany parsing error: {}", msg.as_ref())
-            },
-            Error::Parse(err, msg) => {
-                write!(f, "germany parsing error: {}: {}", msg, err)
+/// Election date from a `#` header line such as "# Bundestagswahl am 24.09.2017"
+/// or "# Bundestagswahl 2017". A year alone is looked up in `ELECTION_DATES`;
+/// other numbers, such as a count in a title, aren't dates, so such lines give `None`.
+fn date_from_header(line: &str) -> Result<Option<Date>, Error> {
+    // "Stand" is the date the figures were published, not of the election.
+    if line.trim_start_matches('#').trim_start().starts_with("Stand") {
+        return Ok(None);
+    }
+    for word in line.split_whitespace().rev() {
+        let word = word.trim_end_matches(',');
+        let parts: Vec<&str> = word.split('.').collect();
+        if let [day, month, year] = parts[..] {
+            if let (Ok(day), Ok(month), Ok(year)) = (day.parse(), month.parse(), year.parse()) {
+                return Date::checked(year, month, day).map(Some);
+            }
+        }
+        if let Ok(date) = word.parse::<Date>() {
+            return Ok(Some(date));
+        }
+        if let Ok(year) = word.parse::<u32>() {
+            if let Some(&(year, month, day)) = ELECTION_DATES.iter().find(|&&(y, _, _)| y == year) {
+                return Ok(Some(Date::new(year, month, day)));
             }
         }
     }
+    Ok(None)
 }
 
-impl std::error::Error for Error {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        match self {
-            Error::Csv(csv) => {
-                Some(csv)
-            },
-            _ => None
-        }
-    }
+/// Reads a results file of the Federal Returning Officer. The election date is
+/// taken from the file's header unless `date` is given. Parties are looked up in `registry`.
+pub fn from_csv(reader: impl io::Read + io::Seek, date: Option<Date>, registry: &parties::Registry) -> Result<(ElectionStage, ElectionResults, Groupings), Error> {
+    let (stage, results, _, groupings) = read_csv(reader, date, registry, false)?;
+    Ok((stage, results, groupings))
 }
 
-impl From<csv::Error> for Error {
-    fn from(e: csv::Error) -> Self {
-        Error::Csv(e)
-    }
+/// Like `from_csv`, but also returns the previous election's results from the
+/// "Vorperiode" columns, if the file has any. These are already given for the
+/// current districts, so they share the same `ElectionStage`.
+pub fn from_csv_with_previous(reader: impl io::Read + io::Seek, date: Option<Date>, registry: &parties::Registry) -> Result<(ElectionStage, ElectionResults, Option<ElectionResults>, Groupings), Error> {
+    read_csv(reader, date, registry, true)
 }
 
-pub fn from_csv(reader: impl io::Read + io::Seek) -> Result<(ElectionStage, ElectionResults, HashMap<u32, Grouping>), Error> {
+fn read_csv(reader: impl io::Read + io::Seek, mut date: Option<Date>, registry: &parties::Registry, with_previous: bool) -> Result<(ElectionStage, ElectionResults, Option<ElectionResults>, Groupings), Error> {
     let mut r = csv::ReaderBuilder::new().delimiter(b';').has_headers(false).flexible(true).from_reader(reader);
 
     #[derive(Default)]
@@ -57,34 +69,39 @@ pub fn from_csv(reader: impl io::Read + io::Seek) -> Result<(ElectionStage, Elec
         pub name: usize,
         pub parent: usize,
         pub population: usize,
+        pub voters: Option<usize>,
+        pub invalid: Option<usize>,
         pub parties: HashMap<PartyID, usize>,
     }
 
     let mut pos: Positions = Default::default();
-    let mut date: Option<Date> = None;
+    if let Some(date) = date {
+        date.validate()?;
+    }
 
     let mut parties = HashMap::new();
     let mut areas_pop = HashMap::new();
     let mut areas = HashMap::new();
     let mut districts = HashMap::new();
     let mut district_results = HashMap::new();
+    let mut previous_results = HashMap::new();
     let mut candidates = HashMap::new();
+    // The previous election's candidates, kept apart and added with `ElectionStage::add_election`.
+    let mut previous_candidates = HashMap::new();
+    let mut previous_district_candidates: HashMap<DistrictID, HashSet<CandidateID>> = HashMap::new();
+    let mut previous_area_candidates: HashMap<AreaID, HashSet<CandidateID>> = HashMap::new();
 
     let mut candidate_id: CandidateID = 0;
+    let mut previous_candidate_id: CandidateID = 0;
     for record  in r.records() {
         let record = record?;
         if record.len() == 0 {
-            Err(Error::Custom("Record cannot have length 0".into()))?;
+            return Err(Error::Inconsistent("record cannot have length 0".into()));
         }
 
         if record.get(0).unwrap().starts_with("#") {
             if let None = date {
-                let last = record.get(0).unwrap().split(' ').rev().next().unwrap();
-                if let Ok(res) = last.parse::<u32>() {
-                    date = Some(Date::new(res, 1, 1));
-                } else {
-                    dbg!(last);
-                }
+                date = date_from_header(record.get(0).unwrap())?;
             }
 
             continue
@@ -97,25 +114,16 @@ pub fn from_csv(reader: impl io::Read + io::Seek) -> Result<(ElectionStage, Elec
                     "Gebiet" => pos.name = i,
                     "gehört zu" => pos.parent = i,
                     "Wahlberechtigte" => pos.population = i,
-                    "Wähler" | "Ungültige" | "Gültige" => continue,
+                    "Wähler" => pos.voters = Some(i),
+                    "Ungültige" => pos.invalid = Some(i),
+                    "Gültige" => continue,
                     "" => continue,
                     _ => {
-                        let party_data = match value {
-                            "Christlich Demokratische Union Deutschlands" => ("Christian Democratic Union", 0x322f2e, PartyType::Conservative),
-                            "Sozialdemokratische Partei Deutschlands" => ("Social Democrats", 0xe2001a, PartyType::SocialDemocratic),
-                            "DIE LINKE" => ("Linke", 0xbe3075, PartyType::Left),
-                            "BÜNDNIS 90/DIE GRÜNEN" => ("Green", 0x19a329, PartyType::Green),
-                            "Christlich-Soziale Union in Bayern e.V." => ("Christian Social Union", 0x008bc6, PartyType::Conservative),
-                            "Freie Demokratische Partei" => ("Free Democrats", 0xffee00, PartyType::Liberal),
-                            "Alternative für Deutschland" => ("Alternative for Germany", 0x00a0e2, PartyType::Fascist),
-                            _ => (value, 0xaaaaaa, PartyType::Other)
-                        };
-
-                        parties.insert((i / 4) as PartyID, Party {
-                            name: party_data.0.to_owned(),
-                            type_: party_data.2,
-                            color: party_data.1,
-                        });
+                        // Each party has four columns, so its ID is its position among them.
+                        if parties.contains_key(&((i / 4) as PartyID)) {
+                            return Err(Error::Inconsistent(format!("party column {} overlaps another party's columns", value)));
+                        }
+                        parties.insert((i / 4) as PartyID, registry.party("Germany", value));
                         pos.parties.insert((i / 4) as PartyID, i);
                     }
                 };
@@ -127,20 +135,23 @@ pub fn from_csv(reader: impl io::Read + io::Seek) -> Result<(ElectionStage, Elec
             continue
         }
 
-        let id = record.get(pos.id).ok_or(Error::Custom("id col not found".into()))?.parse::<DistrictID>().map_err(|err| Error::Parse(err, "id could not be parsed".into()))?;
-        let name = record.get(pos.name).ok_or(Error::Custom("name col not present".into()))?.to_owned();
-        let parent = match record.get(pos.parent).ok_or(Error::Custom("parent col not present".into()))?.parse::<AreaID>() {
+        let column = |i: usize| record.get(i).ok_or(Error::Missing(Entity::Column, i as u32));
+        let id = column(pos.id)?.parse::<DistrictID>()?;
+        let name = column(pos.name)?.to_owned();
+        let parent = match column(pos.parent)?.parse::<AreaID>() {
             Ok(v) => v,
             Err(e) => continue
         };
         if parent == 99 {
+            let pop = column(pos.population)?.parse::<u32>()?;
             areas.insert(id as AreaID, Area {
                 name: name,
                 districts: HashSet::new(),
                 candidates: HashSet::new(),
-                seats: 0
+                seats: 0,
+                population: Some(pop),
+                parent: None,
             });
-            let pop = record.get(pos.population).ok_or(Error::Custom("Wahlberechtigte col not present".into()))?.parse::<u32>().map_err(|err| Error::Parse(err, "population couldn't be parsed".into()))?;
             areas_pop.insert(id as AreaID, pop);
         } else {
             let mut district = District {
@@ -150,81 +161,177 @@ pub fn from_csv(reader: impl io::Read + io::Seek) -> Result<(ElectionStage, Elec
                 candidates: HashSet::new(),
             };
 
+            // Each of these columns is followed by the previous election's value,
+            // then the second vote and its previous value.
+            let count = |col: Option<usize>| col.and_then(|col| record.get(col)).and_then(|v| v.parse::<u32>().ok());
             let mut results = DistrictResults {
                 party_votes: HashMap::new(),
                 party_list_source: PartyListSource::Area,
                 candidate_votes: HashMap::new(),
+                electorate: count(Some(pos.population)),
+                ballots: count(pos.voters),
+                invalid: count(pos.invalid),
+                invalid_party: count(pos.invalid.map(|i| i + 2)),
+            };
+            let mut previous = DistrictResults {
+                party_votes: HashMap::new(),
+                party_list_source: PartyListSource::Area,
+                candidate_votes: HashMap::new(),
+                electorate: count(Some(pos.population + 1)),
+                ballots: count(pos.voters.map(|i| i + 1)),
+                invalid: count(pos.invalid.map(|i| i + 1)),
+                invalid_party: count(pos.invalid.map(|i| i + 3)),
             };
 
-            // Local candidates
-            for (&party, &pos) in pos.parties.iter() {
-                let first_vote = match record.get(pos).ok_or(Error::Custom(("party col 1st vote ".to_owned() + &parties[&party].name + " not present").into()))?.parse::<u32>() {
-                    Ok(v) => v,
-                    Err(_) => continue,
-                };
+            // Parties in column order, so that candidate IDs are the same on every run.
+            let mut party_columns: Vec<(PartyID, usize)> = pos.parties.iter().map(|(&party, &pos)| (party, pos)).collect();
+            party_columns.sort();
 
-                let cid = candidate_id;
-                candidate_id += 1;
-                candidates.insert(cid, Candidate {
-                    name: None,
-                    party: Some(party),
-                });
-                district.candidates.insert(cid);
+            // Local candidates
+            for &(party, pos) in party_columns.iter() {
+                let first_vote = column(pos)?.parse::<u32>().ok();
+                if let Some(votes) = first_vote {
+                    let cid = candidate_id;
+                    candidate_id += 1;
+                    candidates.insert(cid, Candidate {
+                        name: None,
+                        party: Some(party),
+                        election: None,
+                    });
+                    district.candidates.insert(cid);
+                    results.candidate_votes.insert(cid, votes);
+                }
 
-                results.candidate_votes.insert(cid, first_vote);
+                if let Some(votes) = count(Some(pos + 1)).filter(|_| with_previous) {
+                    let cid = previous_candidate_id;
+                    previous_candidate_id += 1;
+                    previous_candidates.insert(cid, Candidate {
+                        name: None,
+                        party: Some(party),
+                        election: None,
+                    });
+                    previous_district_candidates.entry(id).or_default().insert(cid);
+                    previous.candidate_votes.insert(cid, votes);
+                }
             }
 
             // List candidates
-            for (&party, &pos) in pos.parties.iter() {
-                let second_vote = match record.get(pos+2).ok_or(Error::Custom(("party col 2nd vote ".to_owned() +  &parties[&party].name + " not present").into()))?.parse::<u32>() {
-                    Ok(v) => v,
-                    Err(_) => continue,
-                };
-
-                results.party_votes.insert(party, second_vote);
+            for &(party, pos) in party_columns.iter() {
+                if let Ok(second_vote) = column(pos + 2)?.parse::<u32>() {
+                    results.party_votes.insert(party, second_vote);
+                }
+                if let Some(second_vote) = count(Some(pos + 3)).filter(|_| with_previous) {
+                    previous.party_votes.insert(party, second_vote);
+                }
             }
 
             districts.insert(id as DistrictID, district);
             district_results.insert(id as DistrictID, results);
+            if with_previous {
+                previous_results.insert(id as DistrictID, previous);
+            }
         }
     }
 
     // Add `districts` to areas
-    for (&id, district) in districts.iter() {
-        areas.get_mut(&district.area).unwrap().districts.insert(id);
+    let mut district_ids: Vec<DistrictID> = districts.keys().copied().collect();
+    district_ids.sort();
+    for id in district_ids.iter() {
+        let district = &districts[id];
+        areas.get_mut(&district.area).unwrap().districts.insert(*id);
 
-        for (&party, _) in &district_results[&id].party_votes {
+        let mut list_parties: Vec<PartyID> = district_results[id].party_votes.keys().copied().collect();
+        list_parties.sort();
+        for party in list_parties {
             // Assume 1 candidate for a party for each district where the party recieved votes.
             let cid = candidate_id;
             candidate_id += 1;
-            candidates.insert(cid, Candidate { name: None, party: Some(party) });
+            candidates.insert(cid, Candidate { name: None, party: Some(party), election: None });
 
             areas.get_mut(&district.area).unwrap().candidates.insert(cid);
         }
+
+        if let Some(previous) = previous_results.get(id) {
+            let mut list_parties: Vec<PartyID> = previous.party_votes.keys().copied().collect();
+            list_parties.sort();
+            for party in list_parties {
+                let cid = previous_candidate_id;
+                previous_candidate_id += 1;
+                previous_candidates.insert(cid, Candidate { name: None, party: Some(party), election: None });
+
+                previous_area_candidates.entry(district.area).or_default().insert(cid);
+            }
+        }
     }
 
     let mut groupings = HashMap::new();
-    groupings.insert(1u32, Grouping(districts.keys().map(|&i| {let mut h = HashSet::with_capacity(1); h.insert(i); h }).collect()));
-
-    {
-        dbg!((districts.len() * 2) as SeatCount);
-        let areas_sorted = areas.keys().map(|&x| x).collect::<Vec<_>>();
-        let areas_pop_vec = areas_sorted.iter().map(|&area| areas_pop[&area]).collect::<Vec<_>>();
-        let areas_seats = utils::allocate_sainte_lague(&areas_pop_vec, (districts.len() * 2) as SeatCount);
-        for (idx, &seats) in areas_seats.iter().enumerate() {
-            let local_seats = areas[&areas_sorted[idx]].districts.len() as SeatCount;
-            areas.get_mut(&areas_sorted[idx]).unwrap().seats = seats - local_seats;
-        }
+    groupings.insert(1u32, Grouping(district_ids.iter().map(|&i| {let mut h = HashSet::with_capacity(1); h.insert(i); h }).collect()));
+
+    let areas_seats = apportionment::apportion(&apportionment::Method::Webster, &areas_pop, (districts.len() * 2) as SeatCount);
+    for (id, area) in areas.iter_mut() {
+        area.seats = areas_seats[id].saturating_sub(area.districts.len() as SeatCount);
     }
 
+    let date = date.ok_or(Error::Inconsistent("no election date or Bundestag election year in the file's header, it must be given".into()))?;
+    let mut stage = ElectionStage {
+        candidates, areas, districts, parties, adjacency: None, units: HashMap::new(), unit_adjacency: None
+    };
+
+    let has_previous = previous_results.values().any(|r| !r.candidate_votes.is_empty() || !r.party_votes.is_empty());
+    let previous = if has_previous {
+        // The same areas and districts, with only the candidates of the previous election.
+        let previous_stage = ElectionStage {
+            candidates: previous_candidates,
+            areas: stage.areas.iter().map(|(&id, area)| (id, Area {
+                candidates: previous_area_candidates.remove(&id).unwrap_or_default(),
+                ..area.clone()
+            })).collect(),
+            districts: stage.districts.iter().map(|(&id, district)| (id, District {
+                candidates: previous_district_candidates.remove(&id).unwrap_or_default(),
+                ..district.clone()
+            })).collect(),
+            parties: stage.parties.clone(),
+            adjacency: None,
+            units: HashMap::new(),
+            unit_adjacency: None,
+        };
+        let previous = ElectionResults {
+            date: previous_election(date).ok_or(Error::Inconsistent(format!("no Bundestag election known before {}", date)))?,
+            districts: previous_results,
+            units: HashMap::new(),
+        };
+        Some(stage.add_election(date, &previous_stage, &previous)?)
+    } else {
+        None
+    };
+
     return Ok((
-        ElectionStage {
-            candidates, areas, districts, parties
-        },  
+        stage,
         ElectionResults {
-            date: date.unwrap(),
-            districts: district_results
+            date,
+            districts: district_results,
+            units: HashMap::new(),
         },
+        previous,
         groupings
     ))
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_dates() {
+        assert_eq!(date_from_header("# Bundestagswahl am 24.09.2017").unwrap(), Some(Date::new(2017, 9, 24)));
+        assert_eq!(date_from_header("# Bundestagswahl 2017").unwrap(), Some(Date::new(2017, 9, 24)));
+        assert_eq!(date_from_header("# Stand: 12.10.2017").unwrap(), None);
+        assert!(date_from_header("# Bundestagswahl am 31.02.2017").is_err());
+    }
+
+    #[test]
+    fn other_numbers_are_not_dates() {
+        assert_eq!(date_from_header("# Ergebnisse in 299 Wahlkreisen").unwrap(), None);
+        assert_eq!(date_from_header("# Bundestagswahl 2017, Wahlkreis 12").unwrap(), Some(Date::new(2017, 9, 24)));
+    }
+}
@@ -1,2 +1,5 @@
 pub mod canada;
 pub mod germany;
+pub mod generic;
+pub mod adjacency;
+pub mod transposition;
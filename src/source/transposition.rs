@@ -0,0 +1,63 @@
+use crate::core::*;
+use crate::redistricting::transposition::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io;
+
+#[derive(Deserialize)]
+struct WeightRecord {
+    #[serde(alias = "district", alias = "unit")]
+    from: u32,
+    /// The poll number, when `from` is the poll's district.
+    #[serde(default)]
+    poll: Option<String>,
+    new_district: DistrictID,
+    #[serde(default = "whole")]
+    share: f64,
+    #[serde(default)]
+    name: Option<String>,
+}
+
+fn whole() -> f64 {
+    1.0
+}
+
+/// Reads a weight table with the columns `district,new_district[,share][,name]`,
+/// `district,poll,new_district,...` to map the polls of `stage` by district
+/// and poll number, or `unit,new_district,...` to map units by their `UnitID`.
+/// `share` is the part of the old district's or unit's population in the new
+/// district, `1` if omitted. `name` names the new district and only needs to be given once.
+pub fn from_csv(reader: impl io::Read, stage: &ElectionStage) -> Result<Table, Box<dyn std::error::Error>> {
+    let mut rdr = csv::ReaderBuilder::new().flexible(true).trim(csv::Trim::All).from_reader(reader);
+    let headers = rdr.headers()?;
+    let (source, by_poll) = match (headers.get(0), headers.get(1)) {
+        (Some("district"), Some("poll")) => (Source::Unit, true),
+        (Some("district"), _) => (Source::District, false),
+        (Some("unit"), _) => (Source::Unit, false),
+        _ => return Err("weight table must start with a district or unit column".into()),
+    };
+
+    // Units are numbered by the importer, so polls are found by district and name.
+    let polls: HashMap<(DistrictID, &str), UnitID> = if by_poll {
+        stage.units.iter().map(|(&id, unit)| ((unit.district, unit.name.as_str()), id)).collect()
+    } else {
+        HashMap::new()
+    };
+
+    let mut weights = Vec::new();
+    let mut names = HashMap::new();
+    for record in rdr.deserialize::<WeightRecord>() {
+        let record = record?;
+        let from = match &record.poll {
+            Some(poll) if by_poll => *polls.get(&(record.from, poll.as_str()))
+                .ok_or(format!("no poll {} in district {}", poll, record.from))?,
+            None if by_poll => return Err(format!("no poll given for district {}", record.from).into()),
+            _ => record.from,
+        };
+        if let Some(name) = record.name.filter(|n| !n.is_empty()) {
+            names.insert(record.new_district, name);
+        }
+        weights.push(Weight { from, to: record.new_district, share: record.share });
+    }
+    Ok(Table { source, weights, names })
+}
@@ -10,11 +10,13 @@ pub struct Props {
     pub stage: Weak<ElectionStage>,
     pub results: Weak<ElectionResults>,
     pub district: Option<DistrictID>,
+    pub language: String,
 }
 
 impl PartialEq for Props {
     fn eq(&self, other: &Props) -> bool {
         self.stage.ptr_eq(&other.stage) && self.results.ptr_eq(&other.results) && self.district == other.district
+            && self.language == other.language
     }
 }
 
@@ -50,19 +52,48 @@ impl Component for Info {
     fn view(&self) -> Html {
         let stage = self.props.stage.upgrade().unwrap();
         let results = self.props.results.upgrade();
+        // The abbreviation if there's one, with the full name on hover.
+        let party_label = |party: PartyID| match stage.parties.get(&party) {
+            Some(party) => {
+                let name = party.name_in(&self.props.language);
+                html!(<span title={name.to_owned()}>{party.abbreviation.as_deref().unwrap_or(name)}</span>)
+            },
+            None => html!(<span>{format!("Party {}", party)}</span>),
+        };
 
         if let Some(id) = self.props.district {
             let district = &stage.districts[&id];
             html!(
                 <div class="dinfo"> 
                     <h3>{&district.name}</h3>
+                    {
+                        match results.as_ref().and_then(|results| results.districts.get(&id)) {
+                            Some(dres) if dres.electorate.is_some() || dres.ballots.is_some() => html!(
+                                <div class="dinfo-turnout">
+                                    {
+                                        if let Some(turnout) = dres.turnout() {
+                                            html!(<span class="turnout">{format!("Turnout {:.1}%", turnout * 100.0)}</span>)
+                                        } else {
+                                            "".into()
+                                        }
+                                    }
+                                    {
+                                        for [("Electorate", dres.electorate), ("Ballots", dres.ballots), ("Invalid", dres.invalid)].iter()
+                                            .filter_map(|&(label, value)| value.map(|v| html!(<span class="count">{format!("{} {}", label, v)}</span>)))
+                                    }
+                                </div>
+                            ),
+                            _ => "".into()
+                        }
+                    }
                     <>
                         {{
                             let mut candidates: Vec<CandidateID> = district.candidates.iter().map(|&x| x).collect();
                             if let Some(results) = &results {
-                                candidates.sort_by(|&a, &b|
-                                    results.districts[&id].candidate_votes[&b].cmp(&results.districts[&id].candidate_votes[&a])
-                                );
+                                // The stage can have candidates of other elections, which have no votes here.
+                                let votes = &results.districts[&id].candidate_votes;
+                                candidates.retain(|cid| votes.contains_key(cid));
+                                candidates.sort_by(|a, b| votes[b].cmp(&votes[a]));
                             } else {
                                 candidates.sort();
                             }
@@ -72,7 +103,7 @@ impl Component for Info {
                                     <span class="party" style={format!("color:{}", color_to_hex(
                                         stage.candidates[&cid].party.map(|party| stage.parties[&party].color).unwrap_or(0xaaaaaa)
                                     ))}>
-                                        {stage.candidates[&cid].party.map(|party| stage.parties[&party].name.as_str()).unwrap_or("Independent")}</span>
+                                        {stage.candidates[&cid].party.map(party_label).unwrap_or_else(|| "Independent".into())}</span>
                                     <>{if let Some(results) = &results {
                                         html!(<span class="votes">{results.districts[&id].candidate_votes[&cid]}</span>)
                                     } else {"".into()}}</>
@@ -93,7 +124,7 @@ impl Component for Info {
                                     {{
                                         let format_results = |party_id, votes| {
                                             html!(<div class="dinfo-list-votes">
-                                                <span class="party" style={format!("color:{}",color_to_hex(stage.parties[&party_id].color))}>{&stage.parties[&party_id].name}</span>
+                                                <span class="party" style={format!("color:{}",color_to_hex(stage.parties[&party_id].color))}>{party_label(party_id)}</span>
                                                 <span class="votes">{votes}</span>
                                             </div>)
                                         };
@@ -9,12 +9,13 @@ pub struct Props {
     pub stage: Weak<ElectionStage>,
     pub results: Weak<ElectionResults>,
     pub district: Option<DistrictID>,
+    pub level: usize,
     pub root: ComponentLink<Model>, // TODO: is this a reference cycle?
 }
 
 impl PartialEq for Props {
     fn eq(&self, other: &Props) -> bool {
-        self.stage.ptr_eq(&other.stage) && self.results.ptr_eq(&other.results) && self.district == other.district
+        self.stage.ptr_eq(&other.stage) && self.results.ptr_eq(&other.results) && self.district == other.district && self.level == other.level
     }
 }
 
@@ -54,19 +55,39 @@ impl Component for Map {
         let results = self.props.results.upgrade();
         html!(
             <div class="map">
+                {
+                    if stage.levels() > 1 {
+                        html!(<div class="map-levels">
+                            {
+                                for (0..stage.levels()).map(|level| {
+                                    html!(<button class={if level == self.props.level { "active" } else { "" }}
+                                        onclick=self.link.callback(move |_| Msg::SelectLevel(level))>{format!("Level {}", level)}</button>)
+                                })
+                            }
+                        </div>)
+                    } else {
+                        "".into()
+                    }
+                }
                 {{
-                    let mut areas: Vec<u16> = stage.areas.keys().map(|&x| x).collect();
-                    areas.sort();
+                    let areas = stage.areas_at_level(self.props.level);
                     areas.iter().map(|&area_id| {
                         let area = &stage.areas[&area_id];
+                        let area_districts = stage.districts_in(area_id);
                         html!(
                             <div class="map-area">
                                 <h5 class="map-area-name">{&area.name}</h5>
-                                <div class="map-area-districts" style={format!("width: {}px", (32+8) * ((area.districts.len() as f64).sqrt().ceil() as u32))}>
+                                <div class="map-area-districts" style={format!("width: {}px", (32+8) * ((area_districts.len() as f64).sqrt().ceil() as u32))}>
                                     {
                                         {
-                                            let mut districts: Vec<DistrictID> = area.districts.iter().map(|&x| x).collect();
-                                            districts.sort();
+                                            let districts: Vec<DistrictID> = match &stage.adjacency {
+                                                Some(adjacency) => adjacency.order(&area_districts),
+                                                None => {
+                                                    let mut districts: Vec<DistrictID> = area_districts.iter().copied().collect();
+                                                    districts.sort();
+                                                    districts
+                                                }
+                                            };
                                             districts.iter().map(|&id| (id, &stage.districts[&id])).map(|(id, district)| {
                                                 let mut classes = classes!("map-district");
                                                 if Some(id) == self.props.district {
@@ -74,10 +95,12 @@ impl Component for Map {
                                                 }
                                                 
                                                 html!(<button class=classes onclick=self.link.callback(move |_| Msg::SelectDistrict(id)) style={
-                                                    if let Some(results) = &results { "background:".to_string() + &color_to_hex(results.districts[&id].candidate_votes.iter()
-                                                        .reduce(|c1, c2| if c1.1 > c2.1 { c1 } else { c2 })
-                                                        .and_then(|(&c, _)| stage.candidates[&c].party)
-                                                        .map(|party| stage.parties[&party].color)
+                                                    if let Some(results) = &results { "background:".to_string() + &color_to_hex(results.districts.get(&id)
+                                                        .and_then(|r| r.candidate_votes.iter().reduce(|c1, c2| if c1.1 > c2.1 { c1 } else { c2 }))
+                                                        .and_then(|(c, _)| stage.candidates.get(c))
+                                                        .and_then(|c| c.party)
+                                                        .and_then(|party| stage.parties.get(&party))
+                                                        .map(|party| party.color)
                                                         .unwrap_or(0xaaaaaa)) } else { "".to_string() }
                                                 }>{abbr(&district.name)}</button>)
                                             }).collect::<Html>()
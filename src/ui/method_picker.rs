@@ -9,6 +9,7 @@ pub struct MethodPicker {
     link: ComponentLink<Self>,
     root: ComponentLink<Model>,
     method: Option<Box<dyn ElectoralMethod>>,
+    error: Option<String>,
 }
 
 #[derive(Clone, Properties)]
@@ -16,26 +17,110 @@ pub struct Props {
     pub root: ComponentLink<Model>,
 }
 
+#[derive(Clone)]
+pub enum Msg {
+    Select(Box<dyn ElectoralMethod>),
+    SetParam(&'static str, String),
+    None,
+}
+
+impl MethodPicker {
+    fn view_params(&self) -> Html {
+        let method = match &self.method {
+            Some(method) => method,
+            None => return "".into()
+        };
+        let values = method.params();
+
+        html!(
+            <div class="method-params">
+                {
+                    for method.param_specs().into_iter().map(|spec| {
+                        let name = spec.name;
+                        let value = values.get(name).copied().unwrap_or(spec.default);
+                        let input = match spec.type_ {
+                            ParamType::Bool => {
+                                let checked = value.as_bool().unwrap_or(false);
+                                html!(<input type="checkbox" checked=checked
+                                    onclick=self.link.callback(move |_| Msg::SetParam(name, (!checked).to_string()))/>)
+                            },
+                            ParamType::Float { min, max } => html!(<input type="number" min=min.to_string() max=max.to_string() step="any" value=value.to_string()
+                                onchange=self.link.callback(move |e: ChangeData| match e { ChangeData::Value(v) => Msg::SetParam(name, v), _ => Msg::None })/>),
+                            ParamType::Int { min, max } => html!(<input type="number" min=min.to_string() max=max.to_string() step="1" value=value.to_string()
+                                onchange=self.link.callback(move |e: ChangeData| match e { ChangeData::Value(v) => Msg::SetParam(name, v), _ => Msg::None })/>),
+                        };
+                        html!(
+                            <label class="method-param" title=spec.description>
+                                <span class="name">{spec.name}</span>
+                                {input}
+                            </label>
+                        )
+                    })
+                }
+                {
+                    if let Some(error) = &self.error {
+                        html!(<span class="error">{error}</span>)
+                    } else {
+                        "".into()
+                    }
+                }
+            </div>
+        )
+    }
+}
+
 impl Component for MethodPicker {
-    type Message = Box<dyn ElectoralMethod>;
+    type Message = Msg;
     type Properties = Props;
 
     fn view(&self) -> Html {
         html!(
             <div class="method-picker">
-                <button
-                    class={format!("{}", if self.method.is_some() && self.method.as_ref().unwrap().as_any().is::<methods::fptp::FPTP>() { "active" } else { "" } )}
-                    onclick=self.link.callback(|_| Box::new(methods::fptp::FPTP) as Box<dyn ElectoralMethod>)>{"FPTP"}</button>
-                <button
-                    class={format!("{}", if self.method.is_some() && self.method.as_ref().unwrap().as_any().is::<methods::dmp::DMP>() { "active" } else { "" } )}
-                    onclick=self.link.callback(|_| Box::new(methods::dmp::DMP { threshold : 0.05 }) as Box<dyn ElectoralMethod>)>{"DMP"}</button>
+                {
+                    for methods::METHODS.iter().map(|info| {
+                        let active = self.method.as_ref().map(|method| method.id() == info.id).unwrap_or(false);
+                        let title = if info.examples.is_empty() {
+                            info.description.to_owned()
+                        } else {
+                            format!("{}\nUsed in: {}", info.description, info.examples.join(", "))
+                        };
+                        html!(<button
+                            class={if active { "active" } else { "" }} title=title
+                            onclick=self.link.callback(move |_| Msg::Select((info.create)()))>{info.name}</button>)
+                    })
+                }
+                {self.view_params()}
             </div>
         )
     }
 
     fn update(&mut self, msg: Self::Message) -> ShouldRender {
-        self.method = Some(msg.clone());
-        self.root.send_message(super::Msg::ElectoralMethod(msg));
+        match msg {
+            Msg::Select(method) => {
+                self.method = Some(method);
+                self.error = None;
+            },
+            Msg::SetParam(name, value) => {
+                let method = match &mut self.method {
+                    Some(method) => method,
+                    None => return false
+                };
+                let spec = method.param_specs().into_iter().find(|spec| spec.name == name);
+                let result = spec.ok_or(Error::Method(format!("unknown parameter {}", name)))
+                    .and_then(|spec| spec.parse(&value))
+                    .and_then(|value| method.set_param(name, value));
+                if let Err(e) = result {
+                    self.error = Some(e.to_string());
+                    return true;
+                }
+                self.error = None;
+            },
+            Msg::None => return false,
+        }
+
+        if let Some(method) = &self.method {
+            self.root.send_message(super::Msg::ElectoralMethod(method.clone()));
+        }
         true
     }
 
@@ -43,11 +128,12 @@ impl Component for MethodPicker {
         MethodPicker {
             link,
             root: props.root,
-            method: None
+            method: None,
+            error: None,
         }
     }
 
     fn change(&mut self, _props: Self::Properties) -> ShouldRender {
         true
     }
-}
\ No newline at end of file
+}
@@ -2,28 +2,55 @@ extern crate vsim2;
 use vsim2::core::*;
 use std::{any::Any, sync::{Arc,Weak}};
 use yew::prelude::*;
+use yew::format::{Binary, Nothing};
+use yew::services::fetch::{FetchService, FetchTask, Request, Response};
 use vsim2::core;
+use vsim2::grouping;
 
 mod district_info;
 mod map;
 mod parliament;
 mod method_picker;
 
-#[derive(Clone)]
+/// Fetched when the page loads. `index.html` has trunk copy it next to the app.
+const DATASET: &str = "data.elc";
+
 pub struct Model {
     link: ComponentLink<Self>,
+    /// The request for `DATASET`, until it has been loaded.
+    fetch: Option<FetchTask>,
     stage: Option<Arc<ElectionStage>>,
     results: Option<Arc<ElectionResults>>,
     groupings: Option<Arc<Groupings>>,
+    /// The page's language, in which parties are named if the dataset has names in it.
+    language: String,
     district: Option<DistrictID>,
+    level: usize,
     
-    seats: Option<Arc<SeatResult>>
+    seats: Option<Arc<SeatResult>>,
+    error: Option<String>,
 }
 
 #[derive(Clone)]
 pub enum Msg {
     SelectDistrict(DistrictID),
-    ElectoralMethod(Box<dyn ElectoralMethod>)
+    SelectLevel(usize),
+    ElectoralMethod(Box<dyn ElectoralMethod>),
+    Loaded(Result<Vec<u8>, String>),
+}
+
+impl Model {
+    /// Decodes the sections of `data` that the UI uses.
+    fn load(&mut self, data: &[u8]) -> Result<(), core::Error> {
+        let mut dataset = core::elc::Dataset::open(data)?;
+        let (stage, results, groupings) = (dataset.stage()?, dataset.results()?, dataset.groupings()?);
+        self.error = grouping::validate_all(&groupings, &stage).err().map(|e| e.to_string());
+
+        self.stage = Some(Arc::new(stage));
+        self.results = Some(Arc::new(results));
+        self.groupings = if self.error.is_none() { Some(Arc::new(groupings)) } else { None };
+        Ok(())
+    }
 }
 
 impl Component for Model {
@@ -31,30 +58,68 @@ impl Component for Model {
     type Properties = ();
 
     fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
-        let (stage, results, groupings) = core::decode(&mut &include_bytes!("../../dataset/germany-2017/data.elc")[..]).unwrap();
-        
+        let request = Request::get(DATASET).body(Nothing).expect("a GET request without a body is valid");
+        let callback = link.callback(|response: Response<Binary>| {
+            let (meta, body) = response.into_parts();
+            if meta.status.is_success() {
+                Msg::Loaded(body.map_err(|e| e.to_string()))
+            } else {
+                Msg::Loaded(Err(format!("couldn't fetch {}: {}", DATASET, meta.status)))
+            }
+        });
+        let (fetch, error) = match FetchService::fetch_binary(request, callback) {
+            Ok(task) => (Some(task), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+
         Model {
             link,
-            stage: Some(Arc::new(stage)),
-            results: Some(Arc::new(results)),
-            groupings: Some(Arc::new(groupings)),
+            fetch,
+            stage: None,
+            results: None,
+            groupings: None,
+            language: yew::utils::document().document_element()
+                .and_then(|html| html.get_attribute("lang"))
+                .unwrap_or_else(|| "en".to_owned()),
             seats: None,
             district: None,
+            level: 0,
+            error,
         }
     }
 
     fn update(&mut self, msg: Self::Message) -> ShouldRender {
         match msg {
+            Msg::Loaded(data) => {
+                self.fetch = None;
+                if let Err(e) = data.and_then(|data| self.load(&data).map_err(|e| e.to_string())) {
+                    self.error = Some(e);
+                }
+                true
+            },
             Msg::SelectDistrict(district) => {
                 self.district = Some(district);
                 true
             },
+            Msg::SelectLevel(level) => {
+                self.level = level;
+                true
+            },
             Msg::ElectoralMethod(method) => {
                 if let (Some(stage), Some(results), Some(groupings)) = (&self.stage, &self.results, &self.groupings) {
-                    let grouping = groupings.get(&method.district_size()).unwrap_or(groupings.iter().next().unwrap().1);
-                    match method.run(stage.as_ref(), results.as_ref(), grouping) {
-                        Ok(val) => self.seats = Some(Arc::new(val)),
-                        Err(e) => eprintln!("{}", e),
+                    let seats = core::select_groupings(stage, groupings, &method.grouping_sizes())
+                        .and_then(|grouping| method.run(stage.as_ref(), results.as_ref(), &grouping))
+                        // Checked here so that the view can count seats without failing.
+                        .and_then(|seats| utils::seats_by_party(stage, &seats).map(|_| seats));
+                    match seats {
+                        Ok(val) => {
+                            self.seats = Some(Arc::new(val));
+                            self.error = None;
+                        },
+                        Err(e) => {
+                            self.seats = None;
+                            self.error = Some(e.to_string());
+                        },
                     }
                 }
                 true
@@ -74,9 +139,16 @@ impl Component for Model {
                         html!(
                             <>
                                 <method_picker::MethodPicker root=self.link.clone()></method_picker::MethodPicker>
+                                {
+                                    if let Some(error) = &self.error {
+                                        html!(<div class="status error">{error}</div>)
+                                    } else {
+                                        "".into()
+                                    }
+                                }
                                 <div class="parliament-container">{
                                     if let Some(seats) = &self.seats {
-                                        let mut seats: Vec<_> = utils::seats_by_party(stage, seats).iter().map(|(&partyopt, &count)| {
+                                        let mut seats: Vec<_> = utils::seats_by_party(stage, seats).unwrap_or_default().iter().map(|(&partyopt, &count)| {
                                             (partyopt, count)
                                         }).collect();
                                         seats.sort_by(|(a, _), (b, _)| {
@@ -87,25 +159,34 @@ impl Component for Model {
 
                                             a.unwrap_or(PartyID::MAX).cmp(&b.unwrap_or(PartyID::MAX))
                                         });
-                                        parliament::generate(seats.iter().map(|(partyopt, count)| {
+                                        let labels: Vec<(String, u32, u32)> = seats.iter().map(|(partyopt, count)| {
                                             match partyopt {
-                                                Some(pid) => 
-                                                    (stage.parties[&pid].name.as_str(), *count as u32, stage.parties[&pid].color),
-                                                None => ("Independent", *count as u32, 0xaaaaaa)
+                                                Some(pid) => match stage.parties.get(pid) {
+                                                    Some(party) => match &party.abbreviation {
+                                                        Some(abbreviation) => (format!("{} ({})", party.name_in(&self.language), abbreviation), *count as u32, party.color),
+                                                        None => (party.name_in(&self.language).to_owned(), *count as u32, party.color),
+                                                    },
+                                                    None => (format!("Party {}", pid), *count as u32, 0xaaaaaa),
+                                                },
+                                                None => ("Independent".to_owned(), *count as u32, 0xaaaaaa)
                                             }
-                                        }))
+                                        }).collect();
+                                        parliament::generate(labels.iter().map(|(name, count, color)| (name.as_str(), *count, *color)))
                                     } else {
                                         "".into()
                                     }
                                 }</div>
                                 <map::Map
                                     stage=Arc::downgrade(stage) results=self.results.as_ref().map(Arc::downgrade).unwrap_or(Weak::new()) district=self.district
-                                    root=self.link.clone()></map::Map>
+                                    level=self.level root=self.link.clone()></map::Map>
                                 <district_info::Info
-                                    stage=Arc::downgrade(stage) results=self.results.as_ref().map(Arc::downgrade).unwrap_or(Weak::new()) district=self.district>
+                                    stage=Arc::downgrade(stage) results=self.results.as_ref().map(Arc::downgrade).unwrap_or(Weak::new()) district=self.district
+                                    language=self.language.clone()>
                                 </district_info::Info>
                             </>
                         )
+                    } else if let Some(error) = &self.error {
+                        html!(<div class="status error">{error}</div>)
                     } else {
                         html!(<div class="status">{"Loading..."}</div>)
                     }